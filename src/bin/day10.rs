@@ -1,31 +1,407 @@
-fn main() {
-    let input = std::fs::read_to_string("inputs/day10/input").unwrap();
-    let mut numbers: Vec<usize> = input.lines().map(|line| line.parse().unwrap()).collect();
-    numbers.sort_unstable();
+use std::fmt;
+
+/// The tolerances of the adapters and the device.
+#[derive(Debug, Copy, Clone)]
+struct Config {
+    /// The largest difference in joltage an adapter accepts between its input and output.
+    max_gap: usize,
+    /// How much higher the device is rated than the highest adapter.
+    device_offset: usize,
+}
 
-    let mut number_of_jumps = [0, 0, 1];
-    let mut jolts = 0;
-    for rating in numbers.iter() {
-        let jump = rating - jolts;
-        number_of_jumps[jump - 1] += 1;
-        jolts = *rating;
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            max_gap: 3,
+            device_offset: 3,
+        }
     }
+}
 
-    println!("Solution 1: {}", number_of_jumps[0] * number_of_jumps[2]);
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum ChainError {
+    /// No adapter accepts the output of the adapter rated `from`.
+    Gap { from: usize, to: usize },
+    /// The number of arrangements does not fit in a `u128`.
+    Overflow,
+}
+
+impl fmt::Display for ChainError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChainError::Gap { from, to } => {
+                write!(f, "no adapter connects {} jolts to {} jolts", from, to)
+            }
+            ChainError::Overflow => write!(f, "the number of arrangements overflows"),
+        }
+    }
+}
 
-    numbers.insert(0, 0);
-    let mut ways_to_get_there = vec![0usize; numbers.len()];
+/// Returns the joltage of the outlet, every adapter and the device in ascending order.
+fn chain(adapters: &[usize], config: Config) -> Vec<usize> {
+    let mut ratings = adapters.to_vec();
+    ratings.sort_unstable();
+    let device = ratings.last().copied().unwrap_or(0) + config.device_offset;
+    ratings.insert(0, 0);
+    ratings.push(device);
+    ratings
+}
+
+/// Counts the jumps of each size when chaining every adapter, indexed by the size of the jump.
+fn jump_histogram(chain: &[usize], config: Config) -> Result<Vec<usize>, ChainError> {
+    let mut histogram = vec![0; config.max_gap + 1];
+    for pair in chain.windows(2) {
+        let jump = pair[1] - pair[0];
+        if jump == 0 || jump > config.max_gap {
+            return Err(ChainError::Gap {
+                from: pair[0],
+                to: pair[1],
+            });
+        }
+        histogram[jump] += 1;
+    }
+    Ok(histogram)
+}
+
+/// Returns for every entry in the chain the number of arrangements that connect the outlet to it.
+/// The last entry holds the number of arrangements that reach the device.
+fn arrangement_table(chain: &[usize], config: Config) -> Result<Vec<u128>, ChainError> {
+    let mut ways_to_get_there = vec![0u128; chain.len()];
     ways_to_get_there[0] = 1;
-    for i in 0..numbers.len() {
+    for i in 0..chain.len() {
         let ways_to_get_here = ways_to_get_there[i];
-        let rating = numbers[i];
-        for j in 1..=3 {
-            if i + j >= numbers.len() || numbers[i + j] - rating > 3 {
-                break;
+        for j in successors(chain, i, config) {
+            ways_to_get_there[j] = ways_to_get_there[j]
+                .checked_add(ways_to_get_here)
+                .ok_or(ChainError::Overflow)?;
+        }
+    }
+    Ok(ways_to_get_there)
+}
+
+/// Returns the indices of the entries in the chain that can be plugged into entry `i`.
+fn successors(chain: &[usize], i: usize, config: Config) -> impl Iterator<Item = usize> + '_ {
+    (i + 1..chain.len())
+        .take_while(move |&j| chain[j] - chain[i] <= config.max_gap)
+        .filter(move |&j| chain[j] > chain[i])
+}
+
+/// Iterates over every arrangement of adapters that connects the outlet to the device, in
+/// lexicographic order of the adapter ratings.
+struct Arrangements<'a> {
+    chain: &'a [usize],
+    /// For every entry the entries it can be plugged into that still lead to the device, in the
+    /// order they are visited. Plugging in the device directly ends the arrangement, so it sorts
+    /// first.
+    candidates: Vec<Vec<usize>>,
+    /// The entries of the current arrangement and the next candidate to visit for each.
+    stack: Vec<(usize, usize)>,
+}
+
+impl<'a> Arrangements<'a> {
+    fn new(chain: &'a [usize], config: Config) -> Self {
+        let device = chain.len() - 1;
+        let mut leads_to_device = vec![false; chain.len()];
+        leads_to_device[device] = true;
+        let mut candidates = vec![Vec::new(); chain.len()];
+        for i in (0..device).rev() {
+            let mut next: Vec<usize> = successors(chain, i, config)
+                .filter(|&j| leads_to_device[j])
+                .collect();
+            if next.last() == Some(&device) {
+                next.rotate_right(1);
+            }
+            leads_to_device[i] = !next.is_empty();
+            candidates[i] = next;
+        }
+        Arrangements {
+            chain,
+            candidates,
+            stack: vec![(0, 0)],
+        }
+    }
+}
+
+impl<'a> Iterator for Arrangements<'a> {
+    type Item = Vec<usize>;
+
+    fn next(&mut self) -> Option<Vec<usize>> {
+        while let Some((entry, next)) = self.stack.last_mut() {
+            let entry = *entry;
+            if entry == self.chain.len() - 1 {
+                let adapters = self.stack[1..self.stack.len() - 1]
+                    .iter()
+                    .map(|(i, _)| self.chain[*i])
+                    .collect();
+                self.stack.pop();
+                return Some(adapters);
+            }
+            match self.candidates[entry].get(*next) {
+                Some(&candidate) => {
+                    *next += 1;
+                    self.stack.push((candidate, 0));
+                }
+                None => {
+                    self.stack.pop();
+                }
             }
-            ways_to_get_there[i + j] += ways_to_get_here;
         }
+        None
     }
+}
 
-    println!("Solution 2: {}", ways_to_get_there.last().unwrap());
+/// Returns an arrangement that uses as few adapters as possible.
+fn fewest_adapters(chain: &[usize], config: Config) -> Option<Vec<usize>> {
+    let mut fewest: Vec<Option<(usize, usize)>> = vec![None; chain.len()];
+    fewest[0] = Some((0, 0));
+    for i in 0..chain.len() {
+        let adapters = match fewest[i] {
+            Some((adapters, _)) => adapters,
+            None => continue,
+        };
+        for j in successors(chain, i, config) {
+            let better = match fewest[j] {
+                Some((best, _)) => adapters + 1 < best,
+                None => true,
+            };
+            if better {
+                fewest[j] = Some((adapters + 1, i));
+            }
+        }
+    }
+
+    let mut arrangement = Vec::new();
+    let mut entry = chain.len() - 1;
+    fewest[entry]?;
+    while entry != 0 {
+        entry = fewest[entry].unwrap().1;
+        arrangement.push(chain[entry]);
+    }
+    arrangement.pop();
+    arrangement.reverse();
+    Some(arrangement)
+}
+
+/// A small splitmix64 generator, good enough to sample arrangements.
+struct Rng(u64);
+
+impl Rng {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Returns a uniformly distributed number in `0..n`.
+    fn below(&mut self, n: u128) -> u128 {
+        // Reject the top partial range so every remainder is equally likely
+        let zone = u128::MAX - (u128::MAX - n + 1) % n;
+        loop {
+            let value = (self.next_u64() as u128) << 64 | self.next_u64() as u128;
+            if value <= zone {
+                return value % n;
+            }
+        }
+    }
+}
+
+/// Picks an arrangement uniformly at random by walking back from the device, choosing each
+/// previous entry with a probability proportional to the number of arrangements that reach it.
+fn sample_arrangement(
+    chain: &[usize],
+    ways_to_get_there: &[u128],
+    config: Config,
+    rng: &mut Rng,
+) -> Option<Vec<usize>> {
+    let mut entry = chain.len() - 1;
+    if ways_to_get_there[entry] == 0 {
+        return None;
+    }
+
+    let mut arrangement = Vec::new();
+    while entry != 0 {
+        let mut choice = rng.below(ways_to_get_there[entry]);
+        entry = (0..entry)
+            .rev()
+            .take_while(|&i| chain[entry] - chain[i] <= config.max_gap)
+            .filter(|&i| chain[i] < chain[entry])
+            .find(|&i| {
+                if choice < ways_to_get_there[i] {
+                    true
+                } else {
+                    choice -= ways_to_get_there[i];
+                    false
+                }
+            })
+            .unwrap();
+        arrangement.push(chain[entry]);
+    }
+    arrangement.pop();
+    arrangement.reverse();
+    Some(arrangement)
+}
+
+fn main() {
+    let mut config = Config::default();
+    let mut list = 0;
+    let mut samples = 0;
+    let mut seed = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_nanos() as u64;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || -> u64 {
+            args.next()
+                .and_then(|n| n.parse().ok())
+                .unwrap_or_else(|| panic!("{} requires a number", arg))
+        };
+        match arg.as_str() {
+            "--max-gap" => config.max_gap = value() as usize,
+            "--device-offset" => config.device_offset = value() as usize,
+            "--list" => list = value() as usize,
+            "--sample" => samples = value() as usize,
+            "--seed" => seed = value(),
+            _ => panic!("unknown argument '{}'", arg),
+        }
+    }
+
+    let input = std::fs::read_to_string("inputs/day10/input").unwrap();
+    let numbers: Vec<usize> = input.lines().map(|line| line.parse().unwrap()).collect();
+    let chain = chain(&numbers, config);
+
+    match jump_histogram(&chain, config) {
+        Ok(histogram) => {
+            for (jump, count) in histogram.iter().enumerate().skip(1) {
+                println!("Jumps of {}: {}", jump, count);
+            }
+            println!(
+                "Solution 1: {}",
+                histogram[1] * histogram.get(3).copied().unwrap_or(0)
+            );
+        }
+        Err(e) => println!("Not every adapter can be used: {}", e),
+    }
+
+    match arrangement_table(&chain, config) {
+        Ok(ways_to_get_there) => {
+            println!("Solution 2: {}", ways_to_get_there.last().unwrap());
+            let mut rng = Rng(seed);
+            for _ in 0..samples {
+                if let Some(arrangement) =
+                    sample_arrangement(&chain, &ways_to_get_there, config, &mut rng)
+                {
+                    println!("Sample: {:?}", arrangement);
+                }
+            }
+        }
+        Err(e) => println!("Cannot count arrangements: {}", e),
+    }
+
+    for arrangement in Arrangements::new(&chain, config).take(list) {
+        println!("Arrangement: {:?}", arrangement);
+    }
+
+    match fewest_adapters(&chain, config) {
+        Some(adapters) => println!("Fewest adapters: {} {:?}", adapters.len(), adapters),
+        None => println!("The device cannot be reached"),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        arrangement_table, chain, fewest_adapters, jump_histogram, sample_arrangement,
+        Arrangements, ChainError, Config, Rng,
+    };
+    use std::collections::HashMap;
+
+    const EXAMPLE: [usize; 11] = [16, 10, 15, 5, 1, 11, 7, 19, 6, 12, 4];
+
+    fn count_arrangements(chain: &[usize], config: Config) -> Result<u128, ChainError> {
+        Ok(*arrangement_table(chain, config)?.last().unwrap())
+    }
+
+    #[test]
+    fn example() {
+        let config = Config::default();
+        let chain = chain(&EXAMPLE, config);
+        assert_eq!(jump_histogram(&chain, config), Ok(vec![0, 7, 0, 5]));
+        assert_eq!(count_arrangements(&chain, config), Ok(8));
+    }
+
+    #[test]
+    fn configurable_gaps() {
+        let config = Config {
+            max_gap: 4,
+            device_offset: 1,
+        };
+        let chain = chain(&[4, 8, 9], config);
+        assert_eq!(jump_histogram(&chain, config), Ok(vec![0, 2, 0, 0, 2]));
+        assert_eq!(count_arrangements(&chain, config), Ok(2));
+        assert_eq!(
+            jump_histogram(&chain, Config::default()),
+            Err(ChainError::Gap { from: 0, to: 4 })
+        );
+    }
+
+    #[test]
+    fn overflow() {
+        let config = Config::default();
+        let adapters: Vec<usize> = (1..500).collect();
+        assert_eq!(
+            count_arrangements(&chain(&adapters, config), config),
+            Err(ChainError::Overflow)
+        );
+    }
+
+    #[test]
+    fn arrangements() {
+        let config = Config::default();
+        let chain = chain(&EXAMPLE, config);
+        let arrangements: Vec<Vec<usize>> = Arrangements::new(&chain, config).collect();
+        assert_eq!(arrangements.len(), 8);
+        assert_eq!(arrangements[0], vec![1, 4, 5, 6, 7, 10, 11, 12, 15, 16, 19]);
+        assert_eq!(arrangements[7], vec![1, 4, 7, 10, 12, 15, 16, 19]);
+        let mut sorted = arrangements.clone();
+        sorted.sort();
+        assert_eq!(arrangements, sorted);
+
+        assert_eq!(
+            fewest_adapters(&chain, config),
+            Some(vec![1, 4, 7, 10, 12, 15, 16, 19])
+        );
+    }
+
+    #[test]
+    fn prefix_sorts_first() {
+        let config = Config {
+            max_gap: 3,
+            device_offset: 1,
+        };
+        let chain = chain(&[1, 2, 3], config);
+        let arrangements: Vec<Vec<usize>> = Arrangements::new(&chain, config).collect();
+        assert_eq!(arrangements[..2], [vec![1], vec![1, 2]]);
+        assert_eq!(
+            arrangements.len() as u128,
+            count_arrangements(&chain, config).unwrap()
+        );
+    }
+
+    #[test]
+    fn sampling_is_uniform() {
+        let config = Config::default();
+        let chain = chain(&EXAMPLE, config);
+        let table = arrangement_table(&chain, config).unwrap();
+        let mut rng = Rng(2020);
+        let mut counts = HashMap::new();
+        for _ in 0..8000 {
+            let arrangement = sample_arrangement(&chain, &table, config, &mut rng).unwrap();
+            *counts.entry(arrangement).or_insert(0) += 1;
+        }
+        assert_eq!(counts.len(), 8);
+        assert!(counts.values().all(|&count| count > 800 && count < 1200));
+    }
 }
@@ -1,9 +1,14 @@
+use adventofcode2020::frames::{Cell, Frame, FrameArgs, FrameWriter};
+use adventofcode2020::stats::{bounding_box, Stats, StatsArgs, StatsWriter};
+use std::collections::HashMap;
+use std::io::Write;
+use std::ops::RangeInclusive;
+
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
 enum Tile {
     Floor,
     Empty,
     Taken,
-    OutOfBounds,
 }
 
 #[derive(Debug, Clone, Eq, PartialEq)]
@@ -13,18 +18,85 @@ struct Tiles {
     height: usize,
 }
 
+const DIRECTIONS: [(isize, isize); 8] = [
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+    (-1, 0),
+    (1, 0),
+    (-1, 1),
+    (0, 1),
+    (1, 1),
+];
+
+/// Which seats count as the neighbours of a seat.
+#[derive(Debug, Clone)]
+enum Neighbourhood {
+    /// The eight surrounding tiles.
+    Adjacent,
+    /// The first seat visible in each of the eight directions.
+    LineOfSight,
+    /// The tiles at the given offsets, for instance the moves of a knight.
+    Offsets(Vec<(isize, isize)>),
+}
+
+/// A seating policy.
+#[derive(Debug, Clone)]
+struct Rules {
+    neighbourhood: Neighbourhood,
+    /// An empty seat becomes occupied when the number of occupied neighbours is in this range.
+    birth: RangeInclusive<usize>,
+    /// An occupied seat is left when at least this many neighbours are occupied.
+    leave_threshold: usize,
+}
+
+impl Rules {
+    fn adjacent() -> Self {
+        Rules {
+            neighbourhood: Neighbourhood::Adjacent,
+            birth: 0..=0,
+            leave_threshold: 4,
+        }
+    }
+
+    fn line_of_sight() -> Self {
+        Rules {
+            neighbourhood: Neighbourhood::LineOfSight,
+            birth: 0..=0,
+            leave_threshold: 5,
+        }
+    }
+
+    /// Returns whether a tile with this many occupied neighbours changes in the next generation.
+    fn flips(&self, tile: Tile, occupied: usize) -> bool {
+        match tile {
+            Tile::Empty => self.birth.contains(&occupied),
+            Tile::Taken => occupied >= self.leave_threshold,
+            Tile::Floor => false,
+        }
+    }
+}
+
 impl Tiles {
-    fn get_neighbours(&self, x: usize, y: usize) -> impl Iterator<Item = Tile> + '_ {
-        (-1..=1)
-            .flat_map(move |dx| (-1..=1).map(move |dy| (x as isize + dx, y as isize + dy)))
-            .filter(move |(nx, ny)| *nx != x as isize || *ny != y as isize)
-            .map(move |(x, y)| {
-                if x >= self.width as isize || x < 0 || y >= self.height as isize || y < 0 {
-                    Tile::OutOfBounds
-                } else {
-                    self.tiles[y as usize * self.width + x as usize]
-                }
-            })
+    fn frame(&self) -> Frame {
+        let mut frame = Frame::new(self.width, self.height);
+        for (idx, tile) in self.tiles.iter().enumerate() {
+            let cell = match tile {
+                Tile::Floor => Cell::Void,
+                Tile::Empty => Cell::Dead,
+                Tile::Taken => Cell::Alive,
+            };
+            frame.set(idx % self.width, idx / self.width, cell);
+        }
+        frame
+    }
+
+    fn get(&self, x: isize, y: isize) -> Option<Tile> {
+        if x >= 0 && y >= 0 && x < self.width as isize && y < self.height as isize {
+            Some(self.tiles[y as usize * self.width + x as usize])
+        } else {
+            None
+        }
     }
 
     fn get_first_seat_in_direction(
@@ -36,8 +108,8 @@ impl Tiles {
     ) -> Option<(usize, usize)> {
         let mut x = x as isize + dx;
         let mut y = y as isize + dy;
-        while x >= 0 && y >= 0 && x < self.width as isize && y < self.height as isize {
-            match self.tiles[y as usize * self.width + x as usize] {
+        while let Some(tile) = self.get(x, y) {
+            match tile {
                 Tile::Taken | Tile::Empty => return Some((x as usize, y as usize)),
                 _ => {}
             }
@@ -47,20 +119,106 @@ impl Tiles {
         None
     }
 
-    fn get_line_of_sight_map(&self) -> Vec<Vec<usize>> {
-        (0..self.height)
-            .into_iter()
-            .flat_map(|y| (0..self.width).into_iter().map(move |x| (x, y)))
-            .map(|(x, y)| {
-                (-1..=1)
-                    .into_iter()
-                    .flat_map(|dx| (-1..=1).into_iter().map(move |dy| (dx, dy)))
-                    .filter(move |(dx, dy)| *dx != 0 || *dy != 0)
-                    .filter_map(|(dx, dy)| self.get_first_seat_in_direction(x, y, dx, dy))
-                    .map(|(x, y)| y * self.width + x)
-                    .collect()
-            })
-            .collect()
+    /// Returns for every seat the indices of the seats that are its neighbours. Floor tiles have
+    /// no neighbours.
+    fn get_neighbour_map(&self, neighbourhood: &Neighbourhood) -> AdjacencyList {
+        let offsets = match neighbourhood {
+            Neighbourhood::Adjacent | Neighbourhood::LineOfSight => &DIRECTIONS[..],
+            Neighbourhood::Offsets(offsets) => &offsets[..],
+        };
+        let mut map = AdjacencyList {
+            starts: Vec::with_capacity(self.tiles.len() + 1),
+            indices: Vec::new(),
+        };
+        for idx in 0..self.tiles.len() {
+            map.starts.push(map.indices.len());
+            if self.tiles[idx] == Tile::Floor {
+                continue;
+            }
+            let (x, y) = (idx % self.width, idx / self.width);
+            for &(dx, dy) in offsets.iter() {
+                let neighbour = match neighbourhood {
+                    Neighbourhood::LineOfSight => self.get_first_seat_in_direction(x, y, dx, dy),
+                    _ => {
+                        let (nx, ny) = (x as isize + dx, y as isize + dy);
+                        match self.get(nx, ny) {
+                            Some(Tile::Taken) | Some(Tile::Empty) => {
+                                Some((nx as usize, ny as usize))
+                            }
+                            _ => None,
+                        }
+                    }
+                };
+                if let Some((nx, ny)) = neighbour {
+                    map.indices.push(ny * self.width + nx);
+                }
+            }
+        }
+        map.starts.push(map.indices.len());
+        map
+    }
+}
+
+/// A list of tile indices for every tile, stored in a single buffer.
+#[derive(Debug, Clone)]
+struct AdjacencyList {
+    /// The list of tile `i` is `indices[starts[i]..starts[i + 1]]`.
+    starts: Vec<usize>,
+    indices: Vec<usize>,
+}
+
+impl AdjacencyList {
+    fn get(&self, idx: usize) -> &[usize] {
+        &self.indices[self.starts[idx]..self.starts[idx + 1]]
+    }
+
+    /// Returns the list where `j` is in the list of `i` if `i` is in the list of `j`.
+    fn reversed(&self) -> AdjacencyList {
+        let len = self.starts.len() - 1;
+        let mut starts = vec![0; len + 1];
+        for &idx in self.indices.iter() {
+            starts[idx + 1] += 1;
+        }
+        for idx in 0..len {
+            starts[idx + 1] += starts[idx];
+        }
+        let mut next = starts.clone();
+        let mut indices = vec![0; self.indices.len()];
+        for from in 0..len {
+            for &to in self.get(from) {
+                indices[next[to]] = from;
+                next[to] += 1;
+            }
+        }
+        AdjacencyList { starts, indices }
+    }
+
+    /// Returns the lists padded with `padding` to the length of the longest one.
+    fn padded(&self, padding: usize) -> PaddedList {
+        assert!(padding <= u32::MAX as usize, "too many tiles");
+        let tiles = self.starts.len() - 1;
+        let len = (0..tiles).map(|idx| self.get(idx).len()).max().unwrap_or(0);
+        let mut indices = vec![padding as u32; tiles * len];
+        for idx in 0..tiles {
+            for (slot, &to) in indices[idx * len..].iter_mut().zip(self.get(idx)) {
+                *slot = to as u32;
+            }
+        }
+        PaddedList { len, indices }
+    }
+}
+
+/// A list of tile indices of the same length for every tile, so walking a list takes a fixed
+/// number of steps. Stored as `u32` to halve the memory a walk reads.
+#[derive(Debug, Clone)]
+struct PaddedList {
+    len: usize,
+    indices: Vec<u32>,
+}
+
+impl PaddedList {
+    fn get(&self, idx: usize) -> &[u32] {
+        &self.indices[idx * self.len..(idx + 1) * self.len]
     }
 }
 
@@ -89,123 +247,496 @@ fn parse(input: &str) -> Tiles {
     }
 }
 
-fn tick(tiles: &Tiles) -> Tiles {
-    let mut new_tiles = Tiles {
-        tiles: vec![Tile::Floor; tiles.tiles.len()],
-        width: tiles.width,
-        height: tiles.height,
-    };
+/// How a simulation ended.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum Outcome {
+    /// The seating no longer changes after this generation.
+    Stable(usize),
+    /// The seating of generation `start + period` is the seating of generation `start`.
+    Cycle { start: usize, period: usize },
+    /// Neither happened within the generation limit.
+    LimitReached,
+}
 
-    for y in 0..new_tiles.height {
-        for x in 0..new_tiles.width {
-            let idx = y * tiles.width + x;
-            new_tiles.tiles[idx] = match tiles.tiles[idx] {
-                Tile::Empty => {
-                    if tiles.get_neighbours(x, y).any(|t| t == Tile::Taken) {
-                        Tile::Empty
-                    } else {
-                        Tile::Taken
-                    }
-                }
-                Tile::Taken => {
-                    if tiles
-                        .get_neighbours(x, y)
-                        .filter(|t| *t == Tile::Taken)
-                        .count()
-                        >= 4
-                    {
-                        Tile::Empty
-                    } else {
-                        Tile::Taken
-                    }
-                }
-                t => t,
-            }
+/// A random but fixed key for every tile, the hash of a seating is the xor of the keys of the
+/// occupied seats.
+fn zobrist_key(idx: usize) -> u64 {
+    // splitmix64
+    let mut z = (idx as u64)
+        .wrapping_add(1)
+        .wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// Simulates the seating one generation at a time.
+///
+/// Every seat keeps the number of its occupied neighbours, which is updated whenever a seat
+/// changes. A generation only re-evaluates the seats that changed or that observe a seat that
+/// changed in the previous generation. The decisions of a generation are collected before any of
+/// them is applied, so the tiles and counts are updated in place, and the frontier of seats to
+/// re-evaluate is swapped between two buffers.
+///
+/// On layouts where most seats change every generation, whether a seat flips or is already in the
+/// frontier is unpredictable, so `step` fills the buffers without branching on it. For the same
+/// reason every observer list has the same length, padded with an extra slot past the last tile
+/// that counts as always in the frontier.
+struct Simulation {
+    rules: Rules,
+    tiles: Tiles,
+    /// For every seat, the seats that have it as a neighbour, padded with the extra slot.
+    observers: PaddedList,
+    /// For every tile and the extra slot, the number of occupied neighbours.
+    occupied_neighbours: Vec<u32>,
+    population: usize,
+    generation: usize,
+    hash: u64,
+    /// The seats to re-evaluate are `frontier[..frontier_len]`, both buffers can hold every tile
+    /// and the extra slot.
+    frontier: Vec<usize>,
+    frontier_len: usize,
+    next_frontier: Vec<usize>,
+    /// For every tile and the extra slot, whether it is in the next frontier.
+    in_frontier: Vec<bool>,
+    /// The seats that changed in the last generation.
+    changed: Vec<usize>,
+}
+
+impl Simulation {
+    fn new(tiles: Tiles, rules: Rules) -> Self {
+        let len = tiles.tiles.len();
+        let neighbours = tiles.get_neighbour_map(&rules.neighbourhood);
+        let mut occupied_neighbours: Vec<u32> = (0..len)
+            .map(|idx| {
+                neighbours
+                    .get(idx)
+                    .iter()
+                    .filter(|n| tiles.tiles[**n] == Tile::Taken)
+                    .count() as u32
+            })
+            .collect();
+        occupied_neighbours.push(0);
+        let mut frontier: Vec<usize> = (0..len)
+            .filter(|idx| tiles.tiles[*idx] != Tile::Floor)
+            .collect();
+        let frontier_len = frontier.len();
+        frontier.resize(len + 1, 0);
+        let mut in_frontier = vec![false; len + 1];
+        in_frontier[len] = true;
+        for &idx in frontier[..frontier_len].iter() {
+            in_frontier[idx] = true;
+        }
+        Simulation {
+            observers: neighbours.reversed().padded(len),
+            occupied_neighbours,
+            population: tiles.tiles.iter().filter(|t| **t == Tile::Taken).count(),
+            generation: 0,
+            hash: (0..tiles.tiles.len())
+                .filter(|idx| tiles.tiles[*idx] == Tile::Taken)
+                .fold(0, |hash, idx| hash ^ zobrist_key(idx)),
+            frontier,
+            frontier_len,
+            next_frontier: vec![0; len + 1],
+            in_frontier,
+            changed: Vec::new(),
+            rules,
+            tiles,
         }
     }
 
-    new_tiles
-}
+    /// Advances a single generation and returns the number of seats that changed.
+    fn step(&mut self) -> usize {
+        let Simulation {
+            rules,
+            tiles,
+            observers,
+            occupied_neighbours,
+            population,
+            hash,
+            frontier,
+            frontier_len,
+            next_frontier,
+            in_frontier,
+            changed,
+            ..
+        } = self;
 
-fn tick_with_line_of_sight(tiles: &Tiles, line_of_sight: &[Vec<usize>]) -> Tiles {
-    let mut new_tiles = Tiles {
-        tiles: vec![Tile::Floor; tiles.tiles.len()],
-        width: tiles.width,
-        height: tiles.height,
-    };
+        changed.resize(*frontier_len, 0);
+        let mut len = 0;
+        for &idx in frontier[..*frontier_len].iter() {
+            in_frontier[idx] = false;
+            changed[len] = idx;
+            len += rules.flips(tiles.tiles[idx], occupied_neighbours[idx] as usize) as usize;
+        }
+        changed.truncate(len);
 
-    for y in 0..new_tiles.height {
-        for x in 0..new_tiles.width {
-            let idx = y * tiles.width + x;
-            new_tiles.tiles[idx] = match tiles.tiles[idx] {
-                Tile::Empty => {
-                    if line_of_sight[idx]
-                        .iter()
-                        .map(|idx| tiles.tiles[*idx])
-                        .any(|t| t == Tile::Taken)
-                    {
-                        Tile::Empty
-                    } else {
-                        Tile::Taken
-                    }
-                }
-                Tile::Taken => {
-                    if line_of_sight[idx]
-                        .iter()
-                        .map(|idx| tiles.tiles[*idx])
-                        .filter(|t| *t == Tile::Taken)
-                        .count()
-                        >= 5
-                    {
-                        Tile::Empty
-                    } else {
-                        Tile::Taken
-                    }
-                }
-                t => t,
+        let mut len = 0;
+        let mut enqueue = |idx: usize| {
+            next_frontier[len] = idx;
+            len += !in_frontier[idx] as usize;
+            in_frontier[idx] = true;
+        };
+        for &idx in changed.iter() {
+            let taken = tiles.tiles[idx] == Tile::Empty;
+            tiles.tiles[idx] = if taken { Tile::Taken } else { Tile::Empty };
+            *population = *population + 2 * taken as usize - 1;
+            let delta = (2 * taken as u32).wrapping_sub(1);
+            *hash ^= zobrist_key(idx);
+            enqueue(idx);
+            for &observer in observers.get(idx) {
+                let observer = observer as usize;
+                occupied_neighbours[observer] = occupied_neighbours[observer].wrapping_add(delta);
+                enqueue(observer);
             }
         }
+
+        std::mem::swap(frontier, next_frontier);
+        *frontier_len = len;
+        self.generation += 1;
+        self.changed.len()
+    }
+
+    fn stats(&self) -> Stats {
+        let births = self
+            .changed
+            .iter()
+            .filter(|idx| self.tiles.tiles[**idx] == Tile::Taken)
+            .count();
+        let width = self.tiles.width;
+        Stats {
+            generation: self.generation,
+            population: self.population,
+            births,
+            deaths: self.changed.len() - births,
+            bounding_box: bounding_box(
+                (0..self.tiles.tiles.len())
+                    .filter(|idx| self.tiles.tiles[*idx] == Tile::Taken)
+                    .map(|idx| vec![(idx % width) as isize, (idx / width) as isize]),
+            ),
+        }
     }
 
-    new_tiles
+    /// Runs until the seating is stable or repeats, or for at most `max_generations` generations.
+    /// Calls `inspect` with the initial seating and after every generation.
+    ///
+    /// Repeats are found by comparing hashes of the seatings, so a hash collision could report a
+    /// cycle that does not exist. With 64 bit hashes that is unlikely for any feasible number of
+    /// generations.
+    fn run(&mut self, max_generations: usize, mut inspect: impl FnMut(&Simulation)) -> Outcome {
+        let mut seen = HashMap::new();
+        seen.insert(self.hash, self.generation);
+        inspect(self);
+        while self.generation < max_generations {
+            let changed = self.step();
+            inspect(self);
+            if changed == 0 {
+                return Outcome::Stable(self.generation - 1);
+            }
+            if let Some(start) = seen.insert(self.hash, self.generation) {
+                return Outcome::Cycle {
+                    start,
+                    period: self.generation - start,
+                };
+            }
+        }
+        Outcome::LimitReached
+    }
 }
 
-fn main() {
-    let input = std::fs::read_to_string("inputs/day11/input").unwrap();
-    let tiles = parse(&input);
+fn report(
+    name: &str,
+    tiles: &Tiles,
+    rules: Rules,
+    max_generations: usize,
+    frames: Option<FrameWriter>,
+    mut stats: Option<StatsWriter<impl Write>>,
+) {
+    let mut simulation = Simulation::new(tiles.clone(), rules);
+    let outcome = simulation.run(max_generations, |simulation| {
+        if let Some(frames) = &frames {
+            frames
+                .write(simulation.generation, &simulation.tiles.frame())
+                .unwrap();
+        }
+        if let Some(stats) = &mut stats {
+            stats.write(&simulation.stats()).unwrap();
+        }
+    });
+    if let Some(stats) = stats {
+        stats.finish().unwrap();
+    }
+    match outcome {
+        Outcome::Stable(_) => println!("{}: {}", name, simulation.population),
+        Outcome::Cycle { start, period } => println!(
+            "{}: repeats every {} generations from generation {}",
+            name, period, start
+        ),
+        Outcome::LimitReached => println!(
+            "{}: still changing after {} generations",
+            name, max_generations
+        ),
+    }
+}
+
+/// Parses a custom neighbourhood like `1,2;2,1;-1,2` into `(x, y)` offsets.
+fn parse_offsets(offsets: &str) -> Option<Vec<(isize, isize)>> {
+    offsets
+        .split(';')
+        .map(|offset| {
+            let mut coordinates = offset.split(',').map(|n| n.trim().parse().ok());
+            match (coordinates.next(), coordinates.next(), coordinates.next()) {
+                (Some(x), Some(y), None) => Some((x?, y?)),
+                _ => None,
+            }
+        })
+        .collect()
+}
 
-    let count = {
-        let mut tiles = tiles.clone();
-        loop {
-            let new_tiles = tick(&tiles);
-            if new_tiles == tiles {
-                break new_tiles
-                    .tiles
-                    .into_iter()
-                    .filter(|t| *t == Tile::Taken)
-                    .count();
+fn main() {
+    let mut path = "inputs/day11/input".to_owned();
+    let mut max_generations = 100_000;
+    let mut offsets = None;
+    let mut frame_args = FrameArgs::default();
+    let mut stats_args = StatsArgs::default();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if frame_args.parse(&arg, &mut args) || stats_args.parse(&arg, &mut args) {
+            continue;
+        }
+        match arg.as_str() {
+            "--max-generations" => {
+                max_generations = args
+                    .next()
+                    .and_then(|n| n.parse().ok())
+                    .expect("--max-generations requires a number")
             }
-            tiles = new_tiles;
+            "--offsets" => {
+                offsets = Some(
+                    args.next()
+                        .as_deref()
+                        .and_then(parse_offsets)
+                        .expect("--offsets requires offsets like 1,2;2,1;-1,2"),
+                )
+            }
+            _ => path = arg,
         }
+    }
+    let input = std::fs::read_to_string(path).unwrap();
+    let tiles = parse(&input);
+    let frames = frame_args.writer();
+    let frames = |name: &str| {
+        frames
+            .as_ref()
+            .map(|frames| frames.subdirectory(name).unwrap())
+    };
+    let stats = |name: &str| stats_args.writer(name, &["x", "y"]);
+
+    report(
+        "Solution 1",
+        &tiles,
+        Rules::adjacent(),
+        max_generations,
+        frames("adjacent"),
+        stats("adjacent"),
+    );
+    report(
+        "Solution 2",
+        &tiles,
+        Rules::line_of_sight(),
+        max_generations,
+        frames("line-of-sight"),
+        stats("line-of-sight"),
+    );
+    if let Some(offsets) = offsets {
+        let rules = Rules {
+            neighbourhood: Neighbourhood::Offsets(offsets),
+            ..Rules::adjacent()
+        };
+        report(
+            "Custom offsets",
+            &tiles,
+            rules,
+            max_generations,
+            frames("custom"),
+            stats("custom"),
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        parse, parse_offsets, AdjacencyList, Neighbourhood, Outcome, Rules, Simulation, Tile, Tiles,
     };
+    use std::time::Instant;
+
+    const EXAMPLE: &str = "L.LL.LL.LL
+LLLLLLL.LL
+L.L.L..L..
+LLLL.LL.LL
+L.LL.LL.LL
+L.LLLLL.LL
+..L.L.....
+LLLLLLLLLL
+L.LLLLLL.L
+L.LLLLL.LL";
+
+    fn simulate(tiles: &Tiles, rules: &Rules) -> usize {
+        let mut simulation = Simulation::new(tiles.clone(), rules.clone());
+        match simulation.run(1000, |_| {}) {
+            Outcome::Stable(_) => simulation.population,
+            outcome => panic!("{:?}", outcome),
+        }
+    }
+
+    #[test]
+    fn example() {
+        let tiles = parse(EXAMPLE);
+        assert_eq!(simulate(&tiles, &Rules::adjacent()), 37);
+        assert_eq!(simulate(&tiles, &Rules::line_of_sight()), 26);
+    }
 
-    println!("Solution 1: {}", count);
-
-    let line_of_sight_map = tiles.get_line_of_sight_map();
-    let count = {
-        let mut tiles = tiles;
-        loop {
-            let new_tiles = tick_with_line_of_sight(&tiles, &line_of_sight_map);
-            if new_tiles == tiles {
-                break new_tiles
-                    .tiles
-                    .into_iter()
-                    .filter(|t| *t == Tile::Taken)
-                    .count();
+    #[test]
+    fn custom_offsets() {
+        // Seats only see the seat to their right, so every other seat in a row ends up taken
+        let rules = Rules {
+            neighbourhood: Neighbourhood::Offsets(vec![(1, 0)]),
+            birth: 0..=0,
+            leave_threshold: 1,
+        };
+        assert_eq!(simulate(&parse("LLLLL"), &rules), 3);
+
+        assert_eq!(parse_offsets("1,2;-2,1"), Some(vec![(1, 2), (-2, 1)]));
+        assert_eq!(parse_offsets("1,2,3"), None);
+    }
+
+    #[test]
+    fn incremental_counts() {
+        let mut simulation = Simulation::new(parse(EXAMPLE), Rules::adjacent());
+        assert_eq!(simulation.run(1000, |_| {}), Outcome::Stable(5));
+        assert_eq!(simulation.population, 37);
+
+        let rescan = simulation.tiles.get_neighbour_map(&Neighbourhood::Adjacent);
+        for idx in 0..simulation.tiles.tiles.len() {
+            let occupied = rescan
+                .get(idx)
+                .iter()
+                .filter(|n| simulation.tiles.tiles[**n] == Tile::Taken)
+                .count();
+            assert_eq!(simulation.occupied_neighbours[idx] as usize, occupied);
+        }
+    }
+
+    #[test]
+    fn oscillation() {
+        // Two seats that see each other are taken and left together
+        let rules = Rules {
+            neighbourhood: Neighbourhood::Adjacent,
+            birth: 0..=0,
+            leave_threshold: 1,
+        };
+        assert_eq!(
+            Simulation::new(parse("LL"), rules.clone()).run(1000, |_| {}),
+            Outcome::Cycle {
+                start: 0,
+                period: 2
+            }
+        );
+        assert_eq!(
+            Simulation::new(parse("L##"), rules.clone()).run(1000, |_| {}),
+            Outcome::Cycle {
+                start: 1,
+                period: 2
             }
-            tiles = new_tiles;
+        );
+        assert_eq!(
+            Simulation::new(parse("LL"), rules).run(1, |_| {}),
+            Outcome::LimitReached
+        );
+    }
+
+    /// Re-evaluates every seat from the tiles of the previous generation, which is what the
+    /// simulation did before it kept neighbour counts. Returns the number of seats that changed.
+    fn rescan_step(
+        tiles: &Tiles,
+        next: &mut Tiles,
+        neighbours: &AdjacencyList,
+        rules: &Rules,
+    ) -> usize {
+        let mut changed = 0;
+        for idx in 0..tiles.tiles.len() {
+            let occupied = neighbours
+                .get(idx)
+                .iter()
+                .filter(|n| tiles.tiles[**n] == Tile::Taken)
+                .count();
+            next.tiles[idx] = match tiles.tiles[idx] {
+                Tile::Empty if rules.birth.contains(&occupied) => Tile::Taken,
+                Tile::Taken if occupied >= rules.leave_threshold => Tile::Empty,
+                tile => tile,
+            };
+            changed += (next.tiles[idx] != tiles.tiles[idx]) as usize;
         }
-    };
+        changed
+    }
+
+    /// A square layout of pseudo-random seats, of which about one in five is floor.
+    fn layout(size: usize) -> Tiles {
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        let tiles = (0..size * size)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                if state % 5 == 0 {
+                    Tile::Floor
+                } else {
+                    Tile::Empty
+                }
+            })
+            .collect();
+        Tiles {
+            tiles,
+            width: size,
+            height: size,
+        }
+    }
+
+    /// Compares the simulation with a full rescan of every seat on a large layout that keeps
+    /// changing. Run with `cargo test --release --bin day11 -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    fn bench_large_layout() {
+        let tiles = layout(2000);
+        let rules = Rules::adjacent();
+        let generations = 200;
+
+        let start = Instant::now();
+        let neighbours = tiles.get_neighbour_map(&rules.neighbourhood);
+        let mut current = tiles.clone();
+        let mut next = tiles.clone();
+        let mut changes = Vec::new();
+        for _ in 0..generations {
+            changes.push(rescan_step(&current, &mut next, &neighbours, &rules));
+            std::mem::swap(&mut current, &mut next);
+        }
+        let rescan = start.elapsed();
+
+        let start = Instant::now();
+        let mut simulation = Simulation::new(tiles, rules);
+        for &expected in changes.iter() {
+            assert_eq!(simulation.step(), expected);
+        }
+        let incremental = start.elapsed();
+        assert_eq!(simulation.tiles, current);
 
-    println!("Solution 2: {}", count);
+        println!(
+            "{} generations, {} seats changed: rescan {:?}, incremental {:?}",
+            generations,
+            changes.iter().sum::<usize>(),
+            rescan,
+            incremental
+        );
+    }
 }
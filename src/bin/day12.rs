@@ -1,140 +1,792 @@
+use std::fmt;
+use std::io::{self, Write};
+use std::ops::Add;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
 enum Instruction {
     North(isize),
     South(isize),
     East(isize),
     West(isize),
+    /// Turns counter-clockwise by the number of degrees, which may be any angle.
     Left(isize),
+    /// Turns clockwise by the number of degrees, which may be any angle.
     Right(isize),
     Forward(isize),
 }
 
-#[derive(Copy, Clone)]
-enum Direction {
-    North,
-    West,
-    East,
-    South,
-}
-
-impl Direction {
-    fn left(self, degrees: isize) -> Direction {
-        let mut result = self;
-        let mut degrees = degrees;
-        while degrees >= 90 {
-            result = match result {
-                Direction::North => Direction::West,
-                Direction::West => Direction::South,
-                Direction::East => Direction::North,
-                Direction::South => Direction::East,
-            };
-            degrees -= 90
-        }
-        result
-    }
-    fn right(self, degrees: isize) -> Direction {
-        let mut result = self;
-        let mut degrees = degrees;
-        while degrees >= 90 {
-            result = match result {
-                Direction::North => Direction::East,
-                Direction::West => Direction::North,
-                Direction::East => Direction::South,
-                Direction::South => Direction::West,
-            };
-            degrees -= 90
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Instruction::North(v) => write!(f, "N{}", v),
+            Instruction::South(v) => write!(f, "S{}", v),
+            Instruction::East(v) => write!(f, "E{}", v),
+            Instruction::West(v) => write!(f, "W{}", v),
+            Instruction::Left(v) => write!(f, "L{}", v),
+            Instruction::Right(v) => write!(f, "R{}", v),
+            Instruction::Forward(v) => write!(f, "F{}", v),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+enum ParseError {
+    UnknownAction {
+        line: usize,
+        text: String,
+    },
+    InvalidValue {
+        line: usize,
+        text: String,
+    },
+    /// Only turns may be negative, a negative distance is most likely a typo.
+    NegativeDistance {
+        line: usize,
+        text: String,
+    },
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::UnknownAction { line, text } => {
+                write!(f, "line {}: unknown action in '{}'", line, text)
+            }
+            ParseError::InvalidValue { line, text } => {
+                write!(f, "line {}: invalid value in '{}'", line, text)
+            }
+            ParseError::NegativeDistance { line, text } => {
+                write!(f, "line {}: negative distance in '{}'", line, text)
+            }
+        }
+    }
+}
+
+/// How a position that falls between grid points after turning by an angle that is not a
+/// multiple of 90 degrees is moved onto the grid.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum Rounding {
+    /// To the nearest grid point, halfway cases away from zero.
+    Nearest,
+    TowardZero,
+    Floor,
+}
+
+impl Rounding {
+    fn apply(self, value: f64) -> isize {
+        // Values that only miss a grid line due to floating point errors are on it
+        if (value - value.round()).abs() < 1e-9 {
+            return value.round() as isize;
         }
-        result
+        let rounded = match self {
+            Rounding::Nearest => value.round(),
+            Rounding::TowardZero => value.trunc(),
+            Rounding::Floor => value.floor(),
+        };
+        rounded as isize
     }
 }
 
-fn parse(input: &str) -> Vec<Instruction> {
-    input
-        .lines()
-        .map(|line| {
-            let (op, number) = line.split_at(1);
-            let value = number.parse().unwrap();
-            match op {
-                "N" => Instruction::North(value),
-                "S" => Instruction::South(value),
-                "E" => Instruction::East(value),
-                "W" => Instruction::West(value),
-                "L" => Instruction::Left(value),
-                "R" => Instruction::Right(value),
-                "F" => Instruction::Forward(value),
-                _ => unreachable!(),
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+struct Vector {
+    x: isize,
+    y: isize,
+}
+
+impl Vector {
+    fn new(x: isize, y: isize) -> Self {
+        Vector { x, y }
+    }
+
+    /// Rotates counter-clockwise by `degrees`. Rotations by a multiple of 90 degrees are exact,
+    /// the result of any other rotation is rounded onto the grid.
+    fn rotate(self, degrees: isize, rounding: Rounding) -> Vector {
+        let degrees = degrees.rem_euclid(360);
+        match degrees {
+            0 => self,
+            90 => Vector::new(-self.y, self.x),
+            180 => Vector::new(-self.x, -self.y),
+            270 => Vector::new(self.y, -self.x),
+            _ => {
+                let (sin, cos) = (degrees as f64).to_radians().sin_cos();
+                let (x, y) = (self.x as f64, self.y as f64);
+                Vector::new(
+                    rounding.apply(x * cos - y * sin),
+                    rounding.apply(x * sin + y * cos),
+                )
             }
+        }
+    }
+
+    fn scale(self, factor: isize) -> Vector {
+        Vector::new(self.x * factor, self.y * factor)
+    }
+
+    fn manhattan(self) -> isize {
+        self.x.abs() + self.y.abs()
+    }
+}
+
+impl Add for Vector {
+    type Output = Vector;
+
+    fn add(self, other: Vector) -> Vector {
+        Vector::new(self.x + other.x, self.y + other.y)
+    }
+}
+
+/// A heading in degrees counter-clockwise from east, always in `0..360`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+struct Heading(isize);
+
+impl Heading {
+    fn turn(self, degrees: isize) -> Heading {
+        Heading((self.0 + degrees.rem_euclid(360)).rem_euclid(360))
+    }
+
+    /// The displacement of moving `distance` along the heading.
+    fn displacement(self, distance: isize, rounding: Rounding) -> Vector {
+        Vector::new(distance, 0).rotate(self.0, rounding)
+    }
+}
+
+/// How the ship interprets the instructions.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum Mode {
+    /// The directions and turns move and turn the ship itself.
+    Heading,
+    /// The directions and turns move the waypoint, the ship moves towards it.
+    Waypoint,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+struct Ship {
+    position: Vector,
+    heading: Heading,
+    /// The waypoint relative to the ship.
+    waypoint: Vector,
+}
+
+impl Default for Ship {
+    fn default() -> Self {
+        Ship {
+            position: Vector::default(),
+            heading: Heading(0),
+            waypoint: Vector::new(10, 1),
+        }
+    }
+}
+
+impl Ship {
+    fn apply(self, instruction: Instruction, mode: Mode, rounding: Rounding) -> Ship {
+        let (offset, turn) = match instruction {
+            Instruction::North(v) => (Vector::new(0, v), 0),
+            Instruction::South(v) => (Vector::new(0, -v), 0),
+            Instruction::East(v) => (Vector::new(v, 0), 0),
+            Instruction::West(v) => (Vector::new(-v, 0), 0),
+            Instruction::Left(v) => (Vector::default(), v),
+            Instruction::Right(v) => (Vector::default(), -v),
+            Instruction::Forward(v) => {
+                let displacement = match mode {
+                    Mode::Heading => self.heading.displacement(v, rounding),
+                    Mode::Waypoint => self.waypoint.scale(v),
+                };
+                return Ship {
+                    position: self.position + displacement,
+                    ..self
+                };
+            }
+        };
+        match mode {
+            Mode::Heading => Ship {
+                position: self.position + offset,
+                heading: self.heading.turn(turn),
+                ..self
+            },
+            Mode::Waypoint => Ship {
+                waypoint: (self.waypoint + offset).rotate(turn, rounding),
+                ..self
+            },
+        }
+    }
+}
+
+fn navigate(instructions: &[Instruction], mode: Mode, rounding: Rounding) -> Ship {
+    instructions
+        .iter()
+        .fold(Ship::default(), |ship, instruction| {
+            ship.apply(*instruction, mode, rounding)
         })
-        .collect()
 }
 
-fn main() {
-    let input = std::fs::read_to_string("inputs/day12/input").unwrap();
-    let instructions = parse(&input);
-
-    let mut direction = Direction::East;
-    let mut position = (0isize, 0isize);
-    for instr in instructions.iter() {
-        let (new_dir, new_pos) = match instr {
-            Instruction::North(v) => (direction, (position.0, position.1 + *v)),
-            Instruction::South(v) => (direction, (position.0, position.1 - *v)),
-            Instruction::East(v) => (direction, (position.0 + *v, position.1)),
-            Instruction::West(v) => (direction, (position.0 - *v, position.1)),
-            Instruction::Left(v) => (direction.left(*v), position),
-            Instruction::Right(v) => (direction.right(*v), position),
-            Instruction::Forward(v) => (
-                direction,
-                match direction {
-                    Direction::North => (position.0, position.1 + *v),
-                    Direction::West => (position.0 - *v, position.1),
-                    Direction::East => (position.0 + *v, position.1),
-                    Direction::South => (position.0, position.1 - *v),
-                },
-            ),
+/// The instructions that move by `offset`, at most one north or south and one east or west.
+fn moves(offset: Vector) -> Vec<Instruction> {
+    let mut result = Vec::new();
+    match offset.y {
+        y if y > 0 => result.push(Instruction::North(y)),
+        y if y < 0 => result.push(Instruction::South(-y)),
+        _ => {}
+    }
+    match offset.x {
+        x if x > 0 => result.push(Instruction::East(x)),
+        x if x < 0 => result.push(Instruction::West(-x)),
+        _ => {}
+    }
+    result
+}
+
+/// The shortest turn counter-clockwise by `degrees`, if it turns at all.
+fn turn(degrees: isize) -> Option<Instruction> {
+    match degrees.rem_euclid(360) {
+        0 => None,
+        d if d <= 180 => Some(Instruction::Left(d)),
+        d => Some(Instruction::Right(360 - d)),
+    }
+}
+
+/// Returns `k > 0` if `target` is `k` times `v`.
+fn multiple(target: Vector, v: Vector) -> Option<isize> {
+    let k = match (v.x, v.y) {
+        (0, 0) => return None,
+        (0, y) => target.y / y,
+        (x, _) => target.x / x,
+    };
+    if k > 0 && v.scale(k) == target {
+        Some(k)
+    } else {
+        None
+    }
+}
+
+/// Returns a shortest list of instructions that brings the ship back to the origin.
+fn return_to_origin(ship: Ship, mode: Mode, rounding: Rounding) -> Vec<Instruction> {
+    let target = Vector::new(-ship.position.x, -ship.position.y);
+    if target == Vector::default() {
+        return Vec::new();
+    }
+    match mode {
+        Mode::Heading => {
+            // Moving along the heading, rounding makes the distance off by at most one
+            let distance = (target.x as f64).hypot(target.y as f64);
+            for d in (distance.floor() as isize - 1).max(1)..=distance.ceil() as isize + 1 {
+                if ship.heading.displacement(d, rounding) == target {
+                    return vec![Instruction::Forward(d)];
+                }
+            }
+            moves(target)
+        }
+        Mode::Waypoint => {
+            let w = ship.waypoint;
+            if let Some(k) = multiple(target, w) {
+                return vec![Instruction::Forward(k)];
+            }
+
+            // Change the waypoint once and move towards it. Changing one coordinate keeps the
+            // other, which fixes the number of moves.
+            let mut changes = Vec::new();
+            if let Some(k) = multiple(Vector::new(0, target.y), Vector::new(0, w.y)) {
+                if target.x % k == 0 {
+                    changes.push(Vector::new(target.x / k - w.x, 0));
+                }
+            } else if w.y == 0 && target.y == 0 {
+                changes.push(Vector::new(target.x - w.x, 0));
+            }
+            if let Some(k) = multiple(Vector::new(target.x, 0), Vector::new(w.x, 0)) {
+                if target.y % k == 0 {
+                    changes.push(Vector::new(0, target.y / k - w.y));
+                }
+            } else if w.x == 0 && target.x == 0 {
+                changes.push(Vector::new(0, target.y - w.y));
+            }
+            if let Some(&change) = changes.first() {
+                let k = multiple(target, w + change).unwrap();
+                let mut result = moves(change);
+                result.push(Instruction::Forward(k));
+                return result;
+            }
+            let quarter_turns = [90, 180, 270].iter().copied();
+            for degrees in quarter_turns.chain((1..360).filter(|d| d % 90 != 0)) {
+                if let Some(k) = multiple(target, w.rotate(degrees, rounding)) {
+                    return vec![turn(degrees).unwrap(), Instruction::Forward(k)];
+                }
+            }
+
+            let mut result = moves(Vector::new(target.x - w.x, target.y - w.y));
+            result.push(Instruction::Forward(1));
+            result
+        }
+    }
+}
+
+/// Returns a list of instructions that leaves the ship in the same state as `instructions`, for
+/// any rounding, by combining instructions where that does not change the result.
+///
+/// With a heading, moves never affect the heading so they are combined into at most two moves at
+/// the end, and turns are combined up to the next forward move. With a waypoint, the moves and
+/// turns by multiples of 90 degrees between two forward moves are combined into a single turn
+/// and at most two moves. Turns by other angles are rounded so they are kept as they are.
+/// Consecutive forward moves are combined unless rounding makes them differ from one long move.
+fn optimise(instructions: &[Instruction], mode: Mode) -> Vec<Instruction> {
+    let mut result = Vec::new();
+    // Since the last forward move the heading or waypoint `w` changed to `rotation(w) + offset`
+    let mut rotation = 0;
+    let mut offset = Vector::default();
+    let mut heading = Heading(0);
+
+    for instruction in instructions.iter() {
+        let (degrees, moved) = match *instruction {
+            Instruction::North(v) => (0, Vector::new(0, v)),
+            Instruction::South(v) => (0, Vector::new(0, -v)),
+            Instruction::East(v) => (0, Vector::new(v, 0)),
+            Instruction::West(v) => (0, Vector::new(-v, 0)),
+            Instruction::Left(v) => (v, Vector::default()),
+            Instruction::Right(v) => (-v, Vector::default()),
+            Instruction::Forward(0) => continue,
+            Instruction::Forward(v) => {
+                // With a heading the moves are only written at the end
+                let unchanged =
+                    rotation == 0 && (mode == Mode::Heading || offset == Vector::default());
+                let exact = mode == Mode::Waypoint || heading.0 % 90 == 0;
+                if let (true, true, Some(Instruction::Forward(previous))) =
+                    (unchanged, exact, result.last_mut())
+                {
+                    *previous += v;
+                    continue;
+                }
+                heading = heading.turn(rotation);
+                result.extend(turn(rotation));
+                if mode == Mode::Waypoint {
+                    result.extend(moves(offset));
+                    offset = Vector::default();
+                }
+                rotation = 0;
+                result.push(Instruction::Forward(v));
+                continue;
+            }
         };
-        direction = new_dir;
-        position = new_pos;
-    }
-
-    println!("Solution 1: {}", position.0.abs() + position.1.abs());
-
-    let mut waypoint = (10isize, 1isize);
-    let mut position = (0isize, 0isize);
-    for instr in instructions.iter() {
-        let (new_pos, new_waypoint) = match instr {
-            Instruction::North(v) => (position, (waypoint.0, waypoint.1 + *v)),
-            Instruction::South(v) => (position, (waypoint.0, waypoint.1 - *v)),
-            Instruction::East(v) => (position, (waypoint.0 + *v, waypoint.1)),
-            Instruction::West(v) => (position, (waypoint.0 - *v, waypoint.1)),
-            Instruction::Left(v) => (position, rotate_waypoint_left(waypoint, *v)),
-            Instruction::Right(v) => (position, rotate_waypoint_right(waypoint, *v)),
-            Instruction::Forward(v) => (
-                (position.0 + waypoint.0 * *v, position.1 + waypoint.1 * *v),
-                waypoint,
-            ),
+
+        match mode {
+            Mode::Heading => {
+                offset = offset + moved;
+                rotation = (rotation + degrees).rem_euclid(360);
+            }
+            Mode::Waypoint if degrees % 90 == 0 => {
+                offset = offset.rotate(degrees, Rounding::Nearest) + moved;
+                rotation = (rotation + degrees).rem_euclid(360);
+            }
+            Mode::Waypoint => {
+                result.extend(turn(rotation));
+                result.extend(moves(offset));
+                result.extend(turn(degrees));
+                rotation = 0;
+                offset = Vector::default();
+            }
+        }
+    }
+    result.extend(turn(rotation));
+    result.extend(moves(offset));
+    result
+}
+
+/// Returns the state of the ship before the first instruction and after every instruction.
+fn route(instructions: &[Instruction], mode: Mode, rounding: Rounding) -> Vec<Ship> {
+    let mut ships = vec![Ship::default()];
+    for instruction in instructions.iter() {
+        let ship = ships.last().unwrap().apply(*instruction, mode, rounding);
+        ships.push(ship);
+    }
+    ships
+}
+
+/// Writes a row for every state of the route, with the instruction that led to it. Only the
+/// heading or the waypoint is written, depending on which one `mode` uses.
+fn write_route_csv(
+    mut w: impl Write,
+    instructions: &[Instruction],
+    ships: &[Ship],
+    mode: Mode,
+) -> io::Result<()> {
+    writeln!(w, "step,instruction,x,y,heading,waypoint_x,waypoint_y")?;
+    for (step, ship) in ships.iter().enumerate() {
+        let instruction = match step {
+            0 => String::new(),
+            _ => instructions[step - 1].to_string(),
         };
-        position = new_pos;
-        waypoint = new_waypoint;
+        write!(
+            w,
+            "{},{},{},{},",
+            step, instruction, ship.position.x, ship.position.y
+        )?;
+        match mode {
+            Mode::Heading => writeln!(w, "{},,", ship.heading.0)?,
+            Mode::Waypoint => writeln!(w, ",{},{}", ship.waypoint.x, ship.waypoint.y)?,
+        }
+    }
+    Ok(())
+}
+
+/// Draws the route as a polyline with north up. In waypoint mode the waypoint of every state
+/// is drawn as a line from the ship.
+fn write_route_svg(mut w: impl Write, ships: &[Ship], mode: Mode) -> io::Result<()> {
+    let mut points: Vec<Vector> = ships.iter().map(|ship| ship.position).collect();
+    if mode == Mode::Waypoint {
+        points.extend(ships.iter().map(|ship| ship.position + ship.waypoint));
+    }
+    let min_x = points.iter().map(|p| p.x).min().unwrap_or(0);
+    let max_x = points.iter().map(|p| p.x).max().unwrap_or(0);
+    let min_y = points.iter().map(|p| p.y).min().unwrap_or(0);
+    let max_y = points.iter().map(|p| p.y).max().unwrap_or(0);
+    let size = (max_x - min_x).max(max_y - min_y).max(1);
+    let margin = size / 20 + 1;
+
+    writeln!(
+        w,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="{} {} {} {}">"#,
+        min_x - margin,
+        -max_y - margin,
+        max_x - min_x + 2 * margin,
+        max_y - min_y + 2 * margin
+    )?;
+    writeln!(
+        w,
+        r#"<style>* {{ vector-effect: non-scaling-stroke; stroke-width: 1.5; }}</style>"#
+    )?;
+    if mode == Mode::Waypoint {
+        writeln!(w, r##"<g stroke="#3a9fbf" stroke-opacity="0.6">"##)?;
+        for ship in ships.iter() {
+            let tip = ship.position + ship.waypoint;
+            writeln!(
+                w,
+                r#"<line x1="{}" y1="{}" x2="{}" y2="{}"/>"#,
+                ship.position.x, -ship.position.y, tip.x, -tip.y
+            )?;
+        }
+        writeln!(w, "</g>")?;
+    }
+    write!(w, r##"<polyline fill="none" stroke="#d04030" points=""##)?;
+    for (i, ship) in ships.iter().enumerate() {
+        let separator = if i == 0 { "" } else { " " };
+        write!(w, "{}{},{}", separator, ship.position.x, -ship.position.y)?;
+    }
+    writeln!(w, r#""/>"#)?;
+    let radius = size as f64 / 150.0;
+    if let (Some(start), Some(end)) = (ships.first(), ships.last()) {
+        for (ship, colour) in [(start, "#30a050"), (end, "#202020")].iter() {
+            writeln!(
+                w,
+                r#"<circle cx="{}" cy="{}" r="{}" fill="{}"/>"#,
+                ship.position.x, -ship.position.y, radius, colour
+            )?;
+        }
+    }
+    writeln!(w, "</svg>")
+}
+
+fn parse(input: &str) -> Result<Vec<Instruction>, Vec<ParseError>> {
+    let mut instructions = Vec::new();
+    let mut errors = Vec::new();
+    for (i, line) in input.lines().enumerate() {
+        let (line, text) = (i + 1, line.trim().to_owned());
+        let mut chars = text.chars();
+        let action = chars.next();
+        let value: isize = match chars.as_str().parse() {
+            Ok(value) => value,
+            Err(_) => {
+                errors.push(ParseError::InvalidValue { line, text });
+                continue;
+            }
+        };
+        let instruction = match action {
+            Some('N') => Instruction::North(value),
+            Some('S') => Instruction::South(value),
+            Some('E') => Instruction::East(value),
+            Some('W') => Instruction::West(value),
+            Some('L') => Instruction::Left(value),
+            Some('R') => Instruction::Right(value),
+            Some('F') => Instruction::Forward(value),
+            _ => {
+                errors.push(ParseError::UnknownAction { line, text });
+                continue;
+            }
+        };
+        match instruction {
+            Instruction::Left(_) | Instruction::Right(_) => {}
+            _ if value < 0 => {
+                errors.push(ParseError::NegativeDistance { line, text });
+                continue;
+            }
+            _ => {}
+        }
+        instructions.push(instruction);
+    }
+    if errors.is_empty() {
+        Ok(instructions)
+    } else {
+        Err(errors)
+    }
+}
+
+fn join(instructions: &[Instruction]) -> String {
+    let instructions: Vec<String> = instructions.iter().map(|i| i.to_string()).collect();
+    instructions.join(" ")
+}
+
+fn main() {
+    let mut path = "inputs/day12/input".to_owned();
+    let mut rounding = Rounding::Nearest;
+    let mut route_dir = None;
+    let mut plan_return = false;
+    let mut optimise_mode = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--rounding" => {
+                rounding = match args.next().as_deref() {
+                    Some("nearest") => Rounding::Nearest,
+                    Some("toward-zero") => Rounding::TowardZero,
+                    Some("floor") => Rounding::Floor,
+                    _ => panic!("--rounding requires nearest, toward-zero or floor"),
+                }
+            }
+            "--return" => plan_return = true,
+            "--optimise" => {
+                optimise_mode = match args.next().as_deref() {
+                    Some("heading") => Some(Mode::Heading),
+                    Some("waypoint") => Some(Mode::Waypoint),
+                    _ => panic!("--optimise requires heading or waypoint"),
+                }
+            }
+            "--route" => route_dir = Some(args.next().expect("--route requires a directory")),
+            _ => path = arg,
+        }
+    }
+
+    let input = std::fs::read_to_string(path).unwrap();
+    let instructions = match parse(&input) {
+        Ok(instructions) => instructions,
+        Err(errors) => {
+            for error in errors {
+                eprintln!("{}", error);
+            }
+            std::process::exit(1);
+        }
+    };
+
+    let ship = navigate(&instructions, Mode::Heading, rounding);
+    println!("Solution 1: {}", ship.position.manhattan());
+    if plan_return {
+        let plan = return_to_origin(ship, Mode::Heading, rounding);
+        println!("Return with heading: {}", join(&plan));
     }
 
-    println!("Solution 2: {}", position.0.abs() + position.1.abs());
+    let ship = navigate(&instructions, Mode::Waypoint, rounding);
+    println!("Solution 2: {}", ship.position.manhattan());
+    if plan_return {
+        let plan = return_to_origin(ship, Mode::Waypoint, rounding);
+        println!("Return with waypoint: {}", join(&plan));
+    }
 
-    fn rotate_waypoint_left(waypoint: (isize, isize), degrees: isize) -> (isize, isize) {
-        let mut waypoint = waypoint;
-        let mut degrees = degrees;
-        while degrees >= 90 {
-            waypoint = (-waypoint.1, waypoint.0);
-            degrees -= 90;
+    if let Some(mode) = optimise_mode {
+        let optimised = optimise(&instructions, mode);
+        eprintln!(
+            "Optimised {} instructions to {}",
+            instructions.len(),
+            optimised.len()
+        );
+        for instruction in optimised {
+            println!("{}", instruction);
+        }
+    }
+
+    if let Some(dir) = route_dir {
+        let dir = std::path::Path::new(&dir);
+        std::fs::create_dir_all(dir).unwrap();
+        for (mode, name) in [(Mode::Heading, "heading"), (Mode::Waypoint, "waypoint")].iter() {
+            let ships = route(&instructions, *mode, rounding);
+            let create = |extension| {
+                let path = dir.join(format!("{}.{}", name, extension));
+                io::BufWriter::new(std::fs::File::create(path).unwrap())
+            };
+            write_route_csv(create("csv"), &instructions, &ships, *mode).unwrap();
+            write_route_svg(create("svg"), &ships, *mode).unwrap();
         }
-        waypoint
     }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        navigate, optimise, parse, return_to_origin, route, write_route_csv, Heading, Instruction,
+        Mode, ParseError, Rounding, Ship, Vector,
+    };
 
-    fn rotate_waypoint_right(waypoint: (isize, isize), degrees: isize) -> (isize, isize) {
-        let mut waypoint = waypoint;
-        let mut degrees = degrees;
-        while degrees >= 90 {
-            waypoint = (waypoint.1, -waypoint.0);
-            degrees -= 90;
+    #[test]
+    fn example() {
+        let instructions = parse("F10\nN3\nF7\nR90\nF11").unwrap();
+        let ship = navigate(&instructions, Mode::Heading, Rounding::Nearest);
+        assert_eq!(ship.position.manhattan(), 25);
+        let ship = navigate(&instructions, Mode::Waypoint, Rounding::Nearest);
+        assert_eq!(ship.position.manhattan(), 286);
+    }
+
+    #[test]
+    fn arbitrary_angles() {
+        let v = Vector::new(10, 0);
+        assert_eq!(v.rotate(-90, Rounding::Nearest), Vector::new(0, -10));
+        assert_eq!(v.rotate(450, Rounding::Nearest), Vector::new(0, 10));
+        assert_eq!(v.rotate(45, Rounding::Nearest), Vector::new(7, 7));
+        assert_eq!(v.rotate(30, Rounding::Nearest), Vector::new(9, 5));
+        assert_eq!(v.rotate(30, Rounding::TowardZero), Vector::new(8, 5));
+        assert_eq!(v.rotate(-30, Rounding::Floor), Vector::new(8, -5));
+        assert_eq!(Heading(0).turn(-45), Heading(315));
+        assert_eq!(
+            Heading(315).displacement(2, Rounding::Nearest),
+            Vector::new(1, -1)
+        );
+    }
+
+    #[test]
+    fn invalid_input() {
+        assert_eq!(
+            parse("F10\nX3\nN\nS-2\nL-45"),
+            Err(vec![
+                ParseError::UnknownAction {
+                    line: 2,
+                    text: "X3".to_owned()
+                },
+                ParseError::InvalidValue {
+                    line: 3,
+                    text: "N".to_owned()
+                },
+                ParseError::NegativeDistance {
+                    line: 4,
+                    text: "S-2".to_owned()
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn route_csv() {
+        let instructions = parse("F10\nN3\nR90").unwrap();
+        let mut heading = Vec::new();
+        let ships = route(&instructions, Mode::Heading, Rounding::Nearest);
+        write_route_csv(&mut heading, &instructions, &ships, Mode::Heading).unwrap();
+        assert_eq!(
+            String::from_utf8(heading).unwrap(),
+            "step,instruction,x,y,heading,waypoint_x,waypoint_y\n\
+             0,,0,0,0,,\n\
+             1,F10,10,0,0,,\n\
+             2,N3,10,3,0,,\n\
+             3,R90,10,3,270,,\n"
+        );
+
+        let mut waypoint = Vec::new();
+        let ships = route(&instructions, Mode::Waypoint, Rounding::Nearest);
+        write_route_csv(&mut waypoint, &instructions, &ships, Mode::Waypoint).unwrap();
+        assert_eq!(
+            String::from_utf8(waypoint).unwrap(),
+            "step,instruction,x,y,heading,waypoint_x,waypoint_y\n\
+             0,,0,0,,10,1\n\
+             1,F10,100,10,,10,1\n\
+             2,N3,100,10,,10,4\n\
+             3,R90,100,10,,4,-10\n"
+        );
+    }
+
+    fn ship(position: (isize, isize), waypoint: (isize, isize)) -> Ship {
+        Ship {
+            position: Vector::new(position.0, position.1),
+            heading: Heading(0),
+            waypoint: Vector::new(waypoint.0, waypoint.1),
+        }
+    }
+
+    #[test]
+    fn return_plans() {
+        let plan = |ship, mode| {
+            let plan = return_to_origin(ship, mode, Rounding::Nearest);
+            let end = plan.iter().fold(ship, |ship, instruction| {
+                ship.apply(*instruction, mode, Rounding::Nearest)
+            });
+            assert_eq!(end.position, Vector::default());
+            plan
+        };
+        use Instruction::*;
+        assert_eq!(plan(ship((0, 0), (10, 1)), Mode::Waypoint), vec![]);
+        assert_eq!(
+            plan(ship((17, -8), (10, 1)), Mode::Heading),
+            vec![North(8), West(17)]
+        );
+        assert_eq!(
+            plan(ship((-30, 0), (10, 1)), Mode::Heading),
+            vec![Forward(30)]
+        );
+        assert_eq!(
+            plan(ship((-20, -2), (10, 1)), Mode::Waypoint),
+            vec![Forward(2)]
+        );
+        assert_eq!(
+            plan(ship((-30, -2), (10, 1)), Mode::Waypoint),
+            vec![East(5), Forward(2)]
+        );
+        assert_eq!(
+            plan(ship((0, -20), (10, 0)), Mode::Waypoint),
+            vec![Left(90), Forward(2)]
+        );
+        assert_eq!(
+            plan(ship((-3, -5), (10, 1)), Mode::Waypoint),
+            vec![North(4), West(7), Forward(1)]
+        );
+    }
+
+    #[test]
+    fn optimised_heading() {
+        let instructions = parse("N3\nS5\nL90\nR180\nF10\nF5\nE2\nL45\nF1\nF1").unwrap();
+        assert_eq!(
+            optimise(&instructions, Mode::Heading),
+            parse("R90\nF15\nL45\nF1\nF1\nS2\nE2").unwrap()
+        );
+    }
+
+    #[test]
+    fn optimised_is_equivalent() {
+        // A linear congruential generator is plenty for picking instructions
+        let mut seed = 12345u64;
+        let mut random = |n: u64| {
+            seed = seed
+                .wrapping_mul(6_364_136_223_846_793_005)
+                .wrapping_add(1_442_695_040_888_963_407);
+            ((seed >> 33) % n) as isize
+        };
+        for _ in 0..200 {
+            let instructions: Vec<Instruction> = (0..30)
+                .map(|_| {
+                    let value = random(20);
+                    let angle = [90, 180, 270, 45, 30][random(5) as usize];
+                    match random(7) {
+                        0 => Instruction::North(value),
+                        1 => Instruction::South(value),
+                        2 => Instruction::East(value),
+                        3 => Instruction::West(value),
+                        4 => Instruction::Left(angle),
+                        5 => Instruction::Right(angle),
+                        _ => Instruction::Forward(value % 5),
+                    }
+                })
+                .collect();
+            for mode in [Mode::Heading, Mode::Waypoint].iter() {
+                let optimised = optimise(&instructions, *mode);
+                assert!(optimised.len() <= instructions.len());
+                for rounding in [Rounding::Nearest, Rounding::TowardZero, Rounding::Floor].iter() {
+                    assert_eq!(
+                        navigate(&optimised, *mode, *rounding),
+                        navigate(&instructions, *mode, *rounding),
+                        "{:?}",
+                        instructions
+                    );
+                }
+            }
         }
-        waypoint
     }
 }
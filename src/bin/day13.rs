@@ -1,49 +1,365 @@
-fn parse(input: &str) -> (isize, Vec<(isize, isize)>) {
-    let mut lines = input.lines();
-    (
-        lines.next().unwrap().parse().unwrap(),
-        lines
-            .next()
-            .unwrap()
-            .split(',')
-            .enumerate()
-            .filter_map(|(i, id)| {
-                if id == "x" {
-                    None
-                } else {
-                    Some((i as isize, id.parse().unwrap()))
-                }
-            })
-            .collect(),
-    )
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fmt;
+
+fn parse(input: &str) -> Result<(isize, Vec<(isize, isize)>), ScheduleError> {
+    let mut lines = input.lines().map(str::trim);
+    let malformed = |line: usize, text: &str| ScheduleError::Malformed {
+        line,
+        text: text.to_owned(),
+    };
+    // A missing line is reported as an empty one
+    let first = lines.next().unwrap_or("");
+    let earliest_time = first.parse().map_err(|_| malformed(1, first))?;
+    let buses = lines
+        .next()
+        .unwrap_or("")
+        .split(',')
+        .enumerate()
+        .filter(|(_, id)| *id != "x")
+        .map(|(i, id)| match id.parse() {
+            Ok(id) if id <= 0 => Err(ScheduleError::InvalidId(id)),
+            Ok(id) => Ok((i as isize, id)),
+            Err(_) => Err(malformed(2, id)),
+        })
+        .collect::<Result<_, _>>()?;
+    Ok((earliest_time, buses))
 }
 
 fn earliest_time_for_bus(earliest_time: isize, id: isize) -> isize {
     (id - (earliest_time % id)) % id
 }
 
-fn inv_mod(x: isize, p: isize) -> isize {
-    (0..p - 2).fold(1, |o, _| (o * x) % p)
+/// Iterates over the departures of the buses, as `(offset, id)`, at or after a time. Yields
+/// `(time, id)` ordered by time and then by id.
+struct Departures {
+    next: BinaryHeap<Reverse<(isize, isize)>>,
+}
+
+impl Departures {
+    fn new(buses: &[(isize, isize)], after: isize) -> Self {
+        Departures {
+            next: buses
+                .iter()
+                .map(|&(_, id)| Reverse((after + earliest_time_for_bus(after, id), id)))
+                .collect(),
+        }
+    }
+}
+
+impl Iterator for Departures {
+    type Item = (isize, isize);
+
+    fn next(&mut self) -> Option<(isize, isize)> {
+        let Reverse((time, id)) = self.next.pop()?;
+        if let Some(next) = time.checked_add(id) {
+            self.next.push(Reverse((next, id)));
+        }
+        Some((time, id))
+    }
+}
+
+/// The times `t` with `t ≡ residue (mod modulus)`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+struct Congruence {
+    residue: i128,
+    modulus: i128,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+enum ScheduleError {
+    /// The text on a line of the schedule is not a time or a list of buses.
+    Malformed { line: usize, text: String },
+    /// Bus IDs are departure intervals and have to be positive.
+    InvalidId(isize),
+    /// No time lets both buses, as `(offset, id)`, depart at their offsets.
+    Conflict((isize, isize), (isize, isize)),
+    /// The period of the schedule does not fit in 128 bits.
+    Overflow,
+}
+
+impl fmt::Display for ScheduleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScheduleError::Malformed { line, text } => {
+                write!(f, "line {}: malformed '{}'", line, text)
+            }
+            ScheduleError::InvalidId(id) => write!(f, "invalid bus id {}", id),
+            ScheduleError::Conflict((offset_a, id_a), (offset_b, id_b)) => write!(
+                f,
+                "bus {} at offset {} and bus {} at offset {} never line up",
+                id_a, offset_a, id_b, offset_b
+            ),
+            ScheduleError::Overflow => write!(f, "the schedule repeats too rarely to compute"),
+        }
+    }
+}
+
+/// Returns `(gcd, coef_a, coef_b)` with `gcd = gcd(a, b) = a * coef_a + b * coef_b`.
+fn extended_gcd(a: i128, b: i128) -> (i128, i128, i128) {
+    let (mut old_remainder, mut remainder) = (a, b);
+    let (mut old_coef_a, mut coef_a) = (1, 0);
+    let (mut old_coef_b, mut coef_b) = (0, 1);
+    while remainder != 0 {
+        let quotient = old_remainder / remainder;
+        let next_remainder = old_remainder - quotient * remainder;
+        old_remainder = std::mem::replace(&mut remainder, next_remainder);
+        let next_coef_a = old_coef_a - quotient * coef_a;
+        old_coef_a = std::mem::replace(&mut coef_a, next_coef_a);
+        let next_coef_b = old_coef_b - quotient * coef_b;
+        old_coef_b = std::mem::replace(&mut coef_b, next_coef_b);
+    }
+    (old_remainder, old_coef_a, old_coef_b)
+}
+
+impl Congruence {
+    /// Returns the first time at or after `time`.
+    fn first_at_or_after(self, time: i128) -> i128 {
+        time + (self.residue - time).rem_euclid(self.modulus)
+    }
+
+    /// The departures of a bus at `offset` minutes after `t`.
+    fn for_bus(offset: isize, id: isize) -> Congruence {
+        let modulus = id as i128;
+        Congruence {
+            residue: (-(offset as i128)).rem_euclid(modulus),
+            modulus,
+        }
+    }
+
+    /// Returns the times that satisfy both congruences, `Ok(None)` if there are none. The moduli
+    /// do not have to be coprime.
+    fn merge(self, other: Congruence) -> Result<Option<Congruence>, ScheduleError> {
+        let (g, p, _) = extended_gcd(self.modulus, other.modulus);
+        let difference = other.residue - self.residue;
+        if difference % g != 0 {
+            return Ok(None);
+        }
+        // self.residue + self.modulus * k satisfies other for k ≡ difference / g * p (mod m)
+        let m = other.modulus / g;
+        let k = ((difference / g).rem_euclid(m))
+            .checked_mul(p.rem_euclid(m))
+            .ok_or(ScheduleError::Overflow)?
+            % m;
+        let modulus = self.modulus.checked_mul(m).ok_or(ScheduleError::Overflow)?;
+        let residue = self
+            .modulus
+            .checked_mul(k)
+            .and_then(|step| step.checked_add(self.residue))
+            .ok_or(ScheduleError::Overflow)?;
+        Ok(Some(Congruence {
+            residue: residue.rem_euclid(modulus),
+            modulus,
+        }))
+    }
+}
+
+/// Returns the times at which every bus, as `(offset, id)`, departs `offset` minutes after the
+/// time, by merging the congruence of every bus into those of the buses before it.
+fn alignment(buses: &[(isize, isize)]) -> Result<Congruence, ScheduleError> {
+    let mut result = Congruence {
+        residue: 0,
+        modulus: 1,
+    };
+    for (i, &(offset, id)) in buses.iter().enumerate() {
+        if id <= 0 {
+            return Err(ScheduleError::InvalidId(id));
+        }
+        result = match result.merge(Congruence::for_bus(offset, id))? {
+            Some(merged) => merged,
+            None => {
+                // A system of congruences is solvable if every pair is, so one of the earlier
+                // buses conflicts with this one
+                let bus = Congruence::for_bus(offset, id);
+                let other = buses[..i]
+                    .iter()
+                    .find(|(offset, id)| {
+                        matches!(Congruence::for_bus(*offset, *id).merge(bus), Ok(None))
+                    })
+                    .unwrap();
+                return Err(ScheduleError::Conflict(*other, (offset, id)));
+            }
+        };
+    }
+    Ok(result)
+}
+
+/// Parses a list of buses like `7:0,13:1`, where every bus is written as `id:offset`.
+fn parse_subset(subset: &str) -> Option<Vec<(isize, isize)>> {
+    subset
+        .split(',')
+        .map(|bus| {
+            let mut parts = bus.split(':');
+            let id = parts.next()?.trim().parse().ok()?;
+            let offset = parts.next()?.trim().parse().ok()?;
+            match parts.next() {
+                Some(_) => None,
+                None => Some((offset, id)),
+            }
+        })
+        .collect()
 }
 
 fn main() {
-    let input = std::fs::read_to_string("inputs/day13/input").unwrap();
-    let (earliest_time, bus_ids) = parse(&input);
+    let mut path = "inputs/day13/input".to_owned();
+    let mut after = None;
+    let mut departures = None;
+    let mut subset = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--after" => {
+                after = Some(
+                    args.next()
+                        .and_then(|t| t.parse().ok())
+                        .expect("--after requires a time"),
+                )
+            }
+            "--departures" => {
+                departures = Some(
+                    args.next()
+                        .and_then(|n| n.parse().ok())
+                        .expect("--departures requires a number"),
+                )
+            }
+            "--align" => {
+                subset = Some(
+                    args.next()
+                        .as_deref()
+                        .and_then(parse_subset)
+                        .expect("--align requires buses like 7:0,13:1"),
+                )
+            }
+            _ => path = arg,
+        }
+    }
+
+    let input = std::fs::read_to_string(path).unwrap();
+    let (earliest_time, bus_ids) = match parse(&input) {
+        Ok(schedule) => schedule,
+        Err(error) => {
+            eprintln!("{}", error);
+            std::process::exit(1);
+        }
+    };
+    let after = after.unwrap_or(earliest_time);
 
-    let (wait_time, earliest_bus) = bus_ids
-        .iter()
-        .min_by_key(|(_, id)| earliest_time_for_bus(earliest_time, *id))
-        .unwrap();
+    match Departures::new(&bus_ids, earliest_time).next() {
+        Some((time, id)) => println!("Solution 1: {}", (time - earliest_time) * id),
+        None => println!("Solution 1: no buses in the schedule"),
+    }
 
-    println!("Solution 1: {}", *earliest_bus * *wait_time);
+    let aligned = |buses: &[(isize, isize)]| match alignment(buses) {
+        Ok(alignment) => alignment,
+        Err(error) => {
+            eprintln!("{}", error);
+            std::process::exit(1);
+        }
+    };
+    println!("Solution 2: {}", aligned(&bus_ids).residue);
+
+    if let Some(n) = departures {
+        for (time, id) in Departures::new(&bus_ids, after).take(n) {
+            println!("Bus {} departs at {}", id, time);
+        }
+    }
+    if let Some(subset) = subset {
+        let alignment = aligned(&subset);
+        println!(
+            "Aligned at {}, every {} minutes",
+            alignment.first_at_or_after(after as i128),
+            alignment.modulus
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{alignment, parse, parse_subset, Congruence, Departures, ScheduleError};
+
+    #[test]
+    fn example() {
+        let (_, buses) = parse("939\n7,13,x,x,59,x,31,19").unwrap();
+        assert_eq!(alignment(&buses).unwrap().residue, 1068781);
+    }
+
+    #[test]
+    fn composite_ids() {
+        assert_eq!(
+            alignment(&[(0, 4), (2, 6), (5, 9)]),
+            Ok(Congruence {
+                residue: 4,
+                modulus: 36
+            })
+        );
+        assert_eq!(
+            alignment(&[(0, 4), (3, 9), (1, 6)]),
+            Err(ScheduleError::Conflict((0, 4), (1, 6)))
+        );
+        assert_eq!(
+            alignment(&[(0, 5), (0, -3)]),
+            Err(ScheduleError::InvalidId(-3))
+        );
+        assert_eq!(parse("939\n7,x,0").err(), Some(ScheduleError::InvalidId(0)));
+        assert_eq!(
+            parse("939\n-7,13").err(),
+            Some(ScheduleError::InvalidId(-7))
+        );
+    }
+
+    #[test]
+    fn malformed() {
+        let malformed = |line: usize, text: &str| {
+            Some(ScheduleError::Malformed {
+                line,
+                text: text.to_owned(),
+            })
+        };
+        assert_eq!(parse("soon\n7,13").err(), malformed(1, "soon"));
+        assert_eq!(parse("939\n7,y,13").err(), malformed(2, "y"));
+        assert_eq!(parse("939").err(), malformed(2, ""));
+        assert_eq!(parse("").err(), malformed(1, ""));
+        assert_eq!(parse("939\nx,x"), Ok((939, Vec::new())));
+    }
 
-    let prod: isize = bus_ids.iter().map(|(_, b)| b).product();
+    #[test]
+    fn overflow() {
+        let n = (1 << 62) + 1;
+        assert_eq!(
+            alignment(&[(0, n), (1, n + 1), (2, n + 2)]),
+            Err(ScheduleError::Overflow)
+        );
+    }
 
-    let result2 = bus_ids
-        .iter()
-        .map(|&(a, b)| -a * (prod / b) * inv_mod(prod / b, b))
-        .sum::<isize>()
-        .rem_euclid(prod);
+    #[test]
+    fn departures() {
+        let (time, buses) = parse("939\n7,13,x,x,59,x,31,19").unwrap();
+        assert_eq!(
+            Departures::new(&buses, time).take(4).collect::<Vec<_>>(),
+            vec![(944, 59), (945, 7), (949, 13), (950, 19)]
+        );
+        assert_eq!(
+            Departures::new(&[(0, 2), (1, 3)], 0)
+                .take(5)
+                .collect::<Vec<_>>(),
+            vec![(0, 2), (0, 3), (2, 2), (3, 3), (4, 2)]
+        );
+    }
 
-    println!("Solution 2: {}", result2);
+    #[test]
+    fn subset_alignment() {
+        let subset = parse_subset("7:0,13:1").unwrap();
+        assert_eq!(subset, vec![(0, 7), (1, 13)]);
+        let alignment = alignment(&subset).unwrap();
+        assert_eq!(
+            alignment,
+            Congruence {
+                residue: 77,
+                modulus: 91
+            }
+        );
+        assert_eq!(alignment.first_at_or_after(100), 168);
+        assert_eq!(alignment.first_at_or_after(77), 77);
+        assert_eq!(parse_subset("7:0,13"), None);
+    }
 }
@@ -1,117 +1,679 @@
 use adventofcode2020::regex;
-use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+/// Addresses and values, of at most 128 bits.
+type Word = u128;
 
 struct Mask {
-    and_mask: usize,
-    or_mask: usize,
-    floating_bits: Vec<usize>,
-    floating_bit_mask: usize,
+    and_mask: Word,
+    or_mask: Word,
+    /// Every bit except the floating ones, including the bits above the word width.
+    floating_bit_mask: Word,
+    text: Rc<str>,
+}
+
+/// A set of addresses where every bit is either fixed or floating, like `1X0X`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+struct Pattern {
+    fixed: Word,
+    /// The values of the fixed bits, floating bits are zero.
+    bits: Word,
+}
+
+impl Pattern {
+    fn address(address: Word) -> Pattern {
+        Pattern {
+            fixed: !0,
+            bits: address,
+        }
+    }
+
+    fn overlaps(self, other: Pattern) -> bool {
+        (self.bits ^ other.bits) & self.fixed & other.fixed == 0
+    }
+
+    /// Returns the addresses of `self` that are not in `other` as disjoint patterns.
+    ///
+    /// Every bit that floats in `self` but is fixed in `other` splits off the addresses where the
+    /// bit differs from `other`, what remains after all of them is inside `other`.
+    fn subtract(self, other: Pattern) -> Vec<Pattern> {
+        if !self.overlaps(other) {
+            return vec![self];
+        }
+        let mut result = Vec::new();
+        let mut remaining = self;
+        let mut split = other.fixed & !self.fixed;
+        while split != 0 {
+            let bit = split & split.wrapping_neg();
+            split &= split - 1;
+            result.push(Pattern {
+                fixed: remaining.fixed | bit,
+                bits: remaining.bits | (!other.bits & bit),
+            });
+            remaining = Pattern {
+                fixed: remaining.fixed | bit,
+                bits: remaining.bits | (other.bits & bit),
+            };
+        }
+        result
+    }
+
+    /// The addresses in both patterns, if they overlap.
+    fn intersection(self, other: Pattern) -> Pattern {
+        Pattern {
+            fixed: self.fixed | other.fixed,
+            bits: self.bits | other.bits,
+        }
+    }
+
+    /// Writes the lowest `width` bits like the masks, or in decimal if no bit floats.
+    fn display(self, width: usize) -> String {
+        if self.fixed == !0 {
+            return self.bits.to_string();
+        }
+        (0..width)
+            .rev()
+            .map(|i| match (self.fixed >> i & 1, self.bits >> i & 1) {
+                (0, _) => 'X',
+                (_, 0) => '0',
+                _ => '1',
+            })
+            .collect()
+    }
+
+    /// The number of addresses, `None` if all 128 bits float.
+    fn len(self) -> Option<Word> {
+        (1 as Word).checked_shl((!self.fixed).count_ones())
+    }
+}
+
+/// A decoded assignment: every address of `pattern` is set to `value`, with the bits of `copied`
+/// taken from the address.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+struct Store {
+    pattern: Pattern,
+    value: Word,
+    copied: Word,
+}
+
+impl Store {
+    /// The sum of the values of all addresses, `None` if it does not fit in a word.
+    fn sum(self) -> Option<Word> {
+        let len = self.pattern.len()?;
+        // Half of the addresses have a floating bit set, all or none a fixed one
+        let fixed = self.value | (self.copied & self.pattern.bits);
+        let floating = self.copied & !self.pattern.fixed;
+        len.checked_mul(fixed)?
+            .checked_add((len / 2).checked_mul(floating)?)
+    }
+}
+
+/// A version of the decoder chip, which decides how the mask changes an assignment.
+trait Decoder {
+    fn decode(&self, mask: &Mask, address: Word, value: Word) -> Store;
+}
+
+/// Overwrites the bits of the value where the mask is `0` or `1`.
+struct V1;
+
+impl Decoder for V1 {
+    fn decode(&self, mask: &Mask, address: Word, value: Word) -> Store {
+        Store {
+            pattern: Pattern::address(address),
+            value: value & mask.and_mask | mask.or_mask,
+            copied: 0,
+        }
+    }
+}
+
+/// Sets the bits of the address where the mask is `1` and writes to every address the `X` bits can
+/// form.
+struct V2;
+
+impl Decoder for V2 {
+    fn decode(&self, mask: &Mask, address: Word, value: Word) -> Store {
+        Store {
+            pattern: Pattern {
+                fixed: mask.floating_bit_mask,
+                bits: (address | mask.or_mask) & mask.floating_bit_mask,
+            },
+            value,
+            copied: 0,
+        }
+    }
+}
+
+/// Decodes addresses like [`V2`], but the `X` bits of the value are taken from the address that
+/// is written.
+struct V3;
+
+impl Decoder for V3 {
+    fn decode(&self, mask: &Mask, address: Word, value: Word) -> Store {
+        Store {
+            value: value & mask.floating_bit_mask,
+            copied: !mask.floating_bit_mask,
+            ..V2.decode(mask, address, value)
+        }
+    }
+}
+
+/// The assignment that wrote a value.
+#[derive(Debug, Clone, Eq, PartialEq)]
+struct Source {
+    line: usize,
+    mask: Rc<str>,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}, mask {}", self.line, self.mask)
+    }
+}
+
+/// A store and the assignment it was decoded from.
+#[derive(Debug, Eq, PartialEq)]
+struct Written {
+    store: Store,
+    source: Source,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+struct Entry {
+    /// The addresses that still hold the value of the store, a subset of its pattern.
+    pattern: Pattern,
+    /// Shared by all entries split off the same store, which keeps entries small to scan.
+    written: Rc<Written>,
+}
+
+impl Entry {
+    /// The values of the addresses, as a pattern where the bits copied from floating address bits
+    /// float.
+    fn value(&self) -> Pattern {
+        let store = self.written.store;
+        let floating = store.copied & !self.pattern.fixed;
+        Pattern {
+            fixed: !floating,
+            bits: store.value | (store.copied & self.pattern.bits),
+        }
+    }
+
+    fn display(&self, width: usize) -> String {
+        format!(
+            "mem[{}] = {}  # {}",
+            self.pattern.display(width),
+            self.value().display(width),
+            self.written.source
+        )
+    }
+}
+
+/// Memory that stores every write as a pattern of addresses. Writing to `n` floating addresses
+/// takes time in the number of earlier writes it overlaps instead of `2^n`.
+#[derive(Debug, Default)]
+struct Memory {
+    /// Disjoint patterns, each with the store that last wrote it.
+    entries: Vec<Entry>,
+}
+
+impl Memory {
+    fn write(&mut self, store: Store, source: Source) {
+        // Only the entries that overlap the new pattern are split. They are swapped out instead of
+        // removed so the entries after them do not move, the order of the entries does not matter
+        let mut remainders = Vec::new();
+        let mut i = 0;
+        while i < self.entries.len() {
+            if !self.entries[i].pattern.overlaps(store.pattern) {
+                i += 1;
+                continue;
+            }
+            let entry = self.entries.swap_remove(i);
+            remainders.extend(
+                entry
+                    .pattern
+                    .subtract(store.pattern)
+                    .into_iter()
+                    .map(|pattern| Entry {
+                        pattern,
+                        ..entry.clone()
+                    }),
+            );
+        }
+        self.entries.extend(remainders);
+        self.entries.push(Entry {
+            pattern: store.pattern,
+            written: Rc::new(Written { store, source }),
+        });
+    }
+
+    /// The sum of all values, `None` if it does not fit in a word.
+    fn sum(&self) -> Option<Word> {
+        self.entries.iter().try_fold(0 as Word, |sum, entry| {
+            let store = Store {
+                pattern: entry.pattern,
+                ..entry.written.store
+            };
+            sum.checked_add(store.sum()?)
+        })
+    }
+
+    /// The entries ordered by their lowest address.
+    fn dump(&self) -> Vec<&Entry> {
+        let mut entries: Vec<_> = self.entries.iter().collect();
+        entries.sort_by_key(|entry| (entry.pattern.bits, !entry.pattern.fixed));
+        entries
+    }
+
+    /// Returns the addresses whose values differ from those in `other`, as the entries of `self`
+    /// and `other` restricted to them, ordered by their lowest address. An address that was only
+    /// written in one of the memories has no entry in the other.
+    fn diff(&self, other: &Memory) -> Vec<(Option<Entry>, Option<Entry>)> {
+        let mut differences = Vec::new();
+        let only = |entry: &Entry, other: &Memory| {
+            other
+                .entries
+                .iter()
+                .fold(vec![entry.pattern], |patterns, other| {
+                    patterns
+                        .into_iter()
+                        .flat_map(|pattern| pattern.subtract(other.pattern))
+                        .collect()
+                })
+                .into_iter()
+                .map(|pattern| Entry {
+                    pattern,
+                    ..entry.clone()
+                })
+                .collect::<Vec<_>>()
+        };
+        for a in self.entries.iter() {
+            for b in other.entries.iter() {
+                if !a.pattern.overlaps(b.pattern) {
+                    continue;
+                }
+                let pattern = a.pattern.intersection(b.pattern);
+                let a = Entry {
+                    pattern,
+                    ..a.clone()
+                };
+                let b = Entry {
+                    pattern,
+                    ..b.clone()
+                };
+                if a.value() != b.value() {
+                    differences.push((Some(a), Some(b)));
+                }
+            }
+            differences.extend(only(a, other).into_iter().map(|a| (Some(a), None)));
+        }
+        for b in other.entries.iter() {
+            differences.extend(only(b, self).into_iter().map(|b| (None, Some(b))));
+        }
+        differences.sort_by_key(|(a, b)| {
+            let pattern = match (a, b) {
+                (Some(entry), _) | (None, Some(entry)) => entry.pattern,
+                (None, None) => unreachable!("a difference has an entry on at least one side"),
+            };
+            (pattern.bits, !pattern.fixed)
+        });
+        differences
+    }
 }
 
 enum Operation {
     Mask(Mask),
-    Assignment { address: usize, value: usize },
-}
-
-fn parse(input: &str) -> Vec<Operation> {
-    input
-        .lines()
-        .map(|line| {
-            if let Some(captures) = regex!("mask = ([X01]{36})").captures(line) {
-                let mut and_mask = !0usize;
-                let mut or_mask = 0;
-                let mut floating_bits = Vec::new();
-                let mut floating_bit_mask = !0usize;
-                for (i, c) in captures[1].chars().rev().enumerate() {
-                    match c {
-                        'X' => {
-                            floating_bits.push(i);
-                            floating_bit_mask &= !(1 << i);
-                        }
-                        '0' => {
-                            and_mask &= !(1 << i);
-                        }
-                        '1' => {
-                            or_mask |= 1 << i;
-                        }
-                        _ => unreachable!(),
+    Assignment {
+        line: usize,
+        address: Word,
+        value: Word,
+    },
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+enum ParseError {
+    Malformed {
+        line: usize,
+        text: String,
+    },
+    MaskWidth {
+        line: usize,
+        width: usize,
+    },
+    /// An address or value that does not fit in the word width.
+    OutOfRange {
+        line: usize,
+        text: String,
+    },
+    /// An assignment before the first mask.
+    MissingMask {
+        line: usize,
+    },
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::Malformed { line, text } => {
+                write!(f, "line {}: malformed '{}'", line, text)
+            }
+            ParseError::MaskWidth { line, width } => {
+                write!(f, "line {}: mask is not {} bits wide", line, width)
+            }
+            ParseError::OutOfRange { line, text } => {
+                write!(
+                    f,
+                    "line {}: '{}' does not fit in the word width",
+                    line, text
+                )
+            }
+            ParseError::MissingMask { line } => {
+                write!(f, "line {}: assignment before the first mask", line)
+            }
+        }
+    }
+}
+
+/// Parses a program for words of `width` bits, at most 128.
+fn parse(input: &str, width: usize) -> Result<Vec<Operation>, Vec<ParseError>> {
+    let mut ops = Vec::new();
+    let mut errors = Vec::new();
+    let mut has_mask = false;
+    let fits = |n: Word| width >= 128 || n >> width == 0;
+    let mask_regex = regex!("^mask = ([X01]+)$");
+    let assignment_regex = regex!("^mem\\[(\\d+)\\] = (\\d+)$");
+    for (i, line) in input.lines().enumerate() {
+        let line_number = i + 1;
+        if let Some(captures) = mask_regex.captures(line) {
+            if captures[1].len() != width {
+                errors.push(ParseError::MaskWidth {
+                    line: line_number,
+                    width,
+                });
+                continue;
+            }
+            let mut and_mask = !0;
+            let mut or_mask = 0;
+            let mut floating_bit_mask = !0;
+            for (i, c) in captures[1].chars().rev().enumerate() {
+                match c {
+                    'X' => {
+                        floating_bit_mask &= !(1 << i);
+                    }
+                    '0' => {
+                        and_mask &= !(1 << i);
+                    }
+                    '1' => {
+                        or_mask |= 1 << i;
                     }
+                    _ => unreachable!(),
                 }
-                Operation::Mask(Mask {
-                    and_mask,
-                    or_mask,
-                    floating_bits,
-                    floating_bit_mask,
-                })
-            } else if let Some(captures) = regex!("mem\\[(\\d+)\\] = (\\d+)").captures(line) {
-                Operation::Assignment {
-                    address: captures[1].parse().unwrap(),
-                    value: captures[2].parse().unwrap(),
+            }
+            has_mask = true;
+            ops.push(Operation::Mask(Mask {
+                and_mask,
+                or_mask,
+                floating_bit_mask,
+                text: captures[1].into(),
+            }));
+        } else if let Some(captures) = assignment_regex.captures(line) {
+            match (captures[1].parse(), captures[2].parse()) {
+                (Ok(address), Ok(value)) if fits(address) && fits(value) => {
+                    if !has_mask {
+                        errors.push(ParseError::MissingMask { line: line_number });
+                    }
+                    ops.push(Operation::Assignment {
+                        line: line_number,
+                        address,
+                        value,
+                    })
                 }
-            } else {
-                unreachable!("{}", line)
+                _ => errors.push(ParseError::OutOfRange {
+                    line: line_number,
+                    text: line.to_owned(),
+                }),
             }
-        })
-        .collect()
+        } else {
+            errors.push(ParseError::Malformed {
+                line: line_number,
+                text: line.to_owned(),
+            });
+        }
+    }
+    if errors.is_empty() {
+        Ok(ops)
+    } else {
+        Err(errors)
+    }
 }
 
-fn main() {
-    let input = std::fs::read_to_string("inputs/day14/input").unwrap();
-    let ops = parse(&input);
-
+fn run(ops: &[Operation], decoder: &dyn Decoder) -> Memory {
     let mut mask = None;
-    let mut values = HashMap::<usize, usize>::new();
+    let mut memory = Memory::default();
     for op in ops.iter() {
         match op {
             Operation::Mask(m) => {
                 mask = Some(m);
             }
-            Operation::Assignment { address, value } => {
-                let mask = mask.unwrap();
-                let new_value = *value & mask.and_mask | mask.or_mask;
-                values.insert(*address, new_value);
+            Operation::Assignment {
+                line,
+                address,
+                value,
+            } => {
+                let mask = mask.expect("assignment before the first mask");
+                let source = Source {
+                    line: *line,
+                    mask: mask.text.clone(),
+                };
+                memory.write(decoder.decode(mask, *address, *value), source);
             }
         }
     }
+    memory
+}
+
+fn decoder(version: &str) -> Option<&'static dyn Decoder> {
+    match version {
+        "v1" => Some(&V1),
+        "v2" => Some(&V2),
+        "v3" => Some(&V3),
+        _ => None,
+    }
+}
 
-    println!("Solution 1: {}", values.values().sum::<usize>());
+fn main() {
+    let mut path = "inputs/day14/input".to_owned();
+    let mut width = 36;
+    let mut extra = None;
+    let mut dump = None;
+    let mut diff = false;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--width" => {
+                width = args
+                    .next()
+                    .and_then(|n| n.parse().ok())
+                    .filter(|n| (1..=128).contains(n))
+                    .expect("--width requires a number of bits up to 128")
+            }
+            "--decoder" => {
+                let version = args.next().unwrap_or_default();
+                let decoder =
+                    decoder(&version).expect("--decoder requires a decoder version, v1, v2 or v3");
+                extra = Some((format!("Decoder {}", version), decoder));
+            }
+            "--dump" => {
+                dump = Some(
+                    args.next()
+                        .as_deref()
+                        .and_then(decoder)
+                        .expect("--dump requires a decoder version, v1, v2 or v3"),
+                )
+            }
+            "--diff" => diff = true,
+            _ => path = arg,
+        }
+    }
 
-    let mut mask = None;
-    let mut values = HashMap::<usize, usize>::new();
-    for op in ops.iter() {
-        match op {
-            Operation::Mask(m) => {
-                mask = Some(m);
+    let input = std::fs::read_to_string(path).unwrap();
+    let ops = match parse(&input, width) {
+        Ok(ops) => ops,
+        Err(errors) => {
+            for error in errors {
+                eprintln!("{}", error);
             }
-            Operation::Assignment { address, value } => {
-                let mask = mask.unwrap();
-
-                let mut addresses = Vec::new();
-                fn build_addresses(
-                    floating_bits: &[usize],
-                    start_address: usize,
-                    addresses: &mut Vec<usize>,
-                ) {
-                    let or_address = start_address | (1 << floating_bits[0]);
-                    let and_address = start_address & !(1 << floating_bits[0]);
-                    for address in [or_address, and_address].iter() {
-                        if floating_bits.len() > 1 {
-                            build_addresses(&floating_bits[1..], *address, addresses);
-                        } else {
-                            addresses.push(*address);
-                        }
-                    }
-                }
+            std::process::exit(1);
+        }
+    };
 
-                let masked_address = (*address & mask.floating_bit_mask) | mask.or_mask;
-                build_addresses(&mask.floating_bits, masked_address, &mut addresses);
+    let mut decoders: Vec<(String, &dyn Decoder)> = vec![
+        ("Solution 1".to_owned(), &V1),
+        ("Solution 2".to_owned(), &V2),
+    ];
+    decoders.extend(extra);
+    for (name, decoder) in decoders.iter() {
+        match run(&ops, *decoder).sum() {
+            Some(sum) => println!("{}: {}", name, sum),
+            None => println!("{}: the sum does not fit in 128 bits", name),
+        }
+    }
 
-                for addr in addresses {
-                    values.insert(addr, *value);
-                }
+    if let Some(decoder) = dump {
+        for entry in run(&ops, decoder).dump() {
+            println!("{}", entry.display(width));
+        }
+    }
+    if diff {
+        // Like a diff of the v1 dump against the v2 one
+        for (v1, v2) in run(&ops, &V1).diff(&run(&ops, &V2)) {
+            if let Some(v1) = v1 {
+                println!("- {}", v1.display(width));
+            }
+            if let Some(v2) = v2 {
+                println!("+ {}", v2.display(width));
             }
         }
     }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{parse, run, Entry, ParseError, V1, V2, V3};
 
-    println!("Solution 2: {}", values.values().sum::<usize>());
+    #[test]
+    fn example() {
+        let ops = parse(
+            "mask = XXXXXXXXXXXXXXXXXXXXXXXXXXXXX1XXXX0X
+mem[8] = 11
+mem[7] = 101
+mem[8] = 0",
+            36,
+        )
+        .unwrap();
+        assert_eq!(run(&ops, &V1).sum(), Some(165));
+
+        let ops = parse(
+            "mask = 000000000000000000000000000000X1001X
+mem[42] = 100
+mask = 00000000000000000000000000000000X0XX
+mem[26] = 1",
+            36,
+        )
+        .unwrap();
+        assert_eq!(run(&ops, &V2).sum(), Some(208));
+    }
+
+    #[test]
+    fn many_floating_bits() {
+        let ops = parse(
+            "mask = 000000XXXXXXXXXXXXXXXXXXXXXXXXXXXXXX
+mem[0] = 3
+mask = 000000XXXXXXXXXXXXXXXXXXXXXXXXXXXXX1
+mem[0] = 5
+mask = 000000000000000000000000000000000000
+mem[2] = 7",
+            36,
+        )
+        .unwrap();
+        assert_eq!(
+            run(&ops, &V2).sum(),
+            Some(3 * (1 << 29) + 5 * (1 << 29) - 3 + 7)
+        );
+    }
+
+    #[test]
+    fn floating_values() {
+        // Addresses 1XX0 receive 1001 with the two middle bits of the address
+        let ops = parse("mask = 1XX0\nmem[0] = 11", 4).unwrap();
+        assert_eq!(run(&ops, &V3).sum(), Some(9 + 11 + 13 + 15));
+        let ops = parse("mask = 1XX0\nmem[1] = 11\nmask = 0001\nmem[11] = 2", 4).unwrap();
+        assert_eq!(run(&ops, &V3).sum(), Some(9 + 11 + 13 + 15 - 11 + 2));
+    }
+
+    #[test]
+    fn dump_and_diff() {
+        let ops = parse("mask = X1X0\nmem[1] = 3\nmem[4] = 5\nmem[1] = 2", 4).unwrap();
+        let display = |entries: Vec<&Entry>| -> Vec<String> {
+            entries.into_iter().map(|entry| entry.display(4)).collect()
+        };
+        assert_eq!(
+            display(run(&ops, &V1).dump()),
+            vec![
+                "mem[1] = 6  # line 4, mask X1X0",
+                "mem[4] = 4  # line 3, mask X1X0"
+            ]
+        );
+        assert_eq!(
+            display(run(&ops, &V2).dump()),
+            vec![
+                "mem[X1X0] = 5  # line 3, mask X1X0",
+                "mem[X1X1] = 2  # line 4, mask X1X0"
+            ]
+        );
+
+        let diff: Vec<_> = run(&ops, &V1)
+            .diff(&run(&ops, &V2))
+            .into_iter()
+            .map(|(v1, v2)| (v1.map(|e| e.display(4)), v2.map(|e| e.display(4))))
+            .collect();
+        assert_eq!(diff.len(), 5);
+        assert_eq!(
+            diff[0],
+            (Some("mem[1] = 6  # line 4, mask X1X0".to_owned()), None)
+        );
+        assert_eq!(
+            diff[1],
+            (
+                Some("mem[4] = 4  # line 3, mask X1X0".to_owned()),
+                Some("mem[4] = 5  # line 3, mask X1X0".to_owned())
+            )
+        );
+        assert_eq!(
+            diff[2],
+            (None, Some("mem[X1X1] = 2  # line 4, mask X1X0".to_owned()))
+        );
+        assert_eq!(
+            diff[4],
+            (None, Some("mem[12] = 5  # line 3, mask X1X0".to_owned()))
+        );
+    }
+
+    #[test]
+    fn word_width() {
+        let mask = format!("mask = {}", "X".repeat(128));
+        let ops = parse(&format!("{}\nmem[{}] = 1", mask, u128::MAX), 128).unwrap();
+        assert_eq!(run(&ops, &V1).sum(), Some(1));
+        assert_eq!(run(&ops, &V2).sum(), None);
+
+        assert_eq!(
+            parse("mask = XX\nmem[4] = 1\nmask = X", 2).err(),
+            Some(vec![
+                ParseError::OutOfRange {
+                    line: 2,
+                    text: "mem[4] = 1".to_owned()
+                },
+                ParseError::MaskWidth { line: 3, width: 2 }
+            ])
+        );
+    }
 }
@@ -1,32 +1,361 @@
-fn count(until_turn: u32, starting_numbers: &[u32]) -> u32 {
-    let mut last_spoken_in_turn: Vec<Option<u32>> = vec![None; until_turn as usize];
-    let mut last_number_spoken = None;
-    for turn in 0..until_turn {
-        let number = if turn < starting_numbers.len() as u32 {
-            starting_numbers[turn as usize]
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+
+/// The most numbers for which the turn they were last spoken in is kept in a dense array, 1GB.
+const DENSE_LIMIT: u64 = 1 << 28;
+
+/// The most bytes an entry of the map of a game takes, including the capacity a `HashMap` keeps
+/// free.
+const SPARSE_ENTRY_BYTES: u64 = 48;
+
+/// Marks a number that has not been spoken yet in the dense array, which stores turns plus one.
+const NEVER: u32 = 0;
+
+/// Yields the numbers spoken in every turn of the memory game.
+///
+/// The turn every number was last spoken in is stored in a dense array of `u32` for numbers
+/// below a limit and in a map for the rare larger ones. Turns that do not fit in the array go to
+/// the map as well, which then takes precedence.
+struct MemoryGame {
+    starting_numbers: Vec<u64>,
+    turn: u64,
+    last_number_spoken: Option<u64>,
+    dense: Vec<u32>,
+    sparse: HashMap<u64, u64>,
+}
+
+impl MemoryGame {
+    /// Creates a game that keeps the numbers below `dense_limit` in the dense array.
+    fn new(starting_numbers: &[u64], dense_limit: u64) -> Self {
+        MemoryGame {
+            starting_numbers: starting_numbers.to_vec(),
+            turn: 0,
+            last_number_spoken: None,
+            dense: vec![NEVER; dense_limit as usize],
+            sparse: HashMap::new(),
+        }
+    }
+
+    /// Returns at most how many bytes a game with `starting_numbers` numbers up to turn `turns`
+    /// takes. The map holds at most the starting numbers and the numbers from `dense_limit` up,
+    /// which are all smaller than `turns`, unless turns past the dense array go to the map too.
+    fn memory(starting_numbers: usize, turns: u64, dense_limit: u64) -> u64 {
+        let sparse = if turns > u32::MAX as u64 {
+            turns
         } else {
-            let last_number_spoken = last_number_spoken.unwrap();
-            last_spoken_in_turn[last_number_spoken as usize]
-                .map(|n| turn - n - 1)
-                .unwrap_or(0)
+            turns.saturating_sub(dense_limit)
         };
+        dense_limit * std::mem::size_of::<u32>() as u64
+            + (sparse + starting_numbers as u64) * SPARSE_ENTRY_BYTES
+    }
 
-        if let Some(last_number_spoken) = last_number_spoken {
-            last_spoken_in_turn[last_number_spoken as usize] = Some(turn - 1);
+    fn last_spoken_in_turn(&self, number: u64) -> Option<u64> {
+        let dense = self
+            .dense
+            .get(number as usize)
+            .filter(|&&turn| turn != NEVER)
+            .map(|&turn| turn as u64 - 1);
+        if number < self.dense.len() as u64 && self.turn <= u32::MAX as u64 {
+            dense
+        } else {
+            self.sparse.get(&number).copied().or(dense)
         }
-        last_number_spoken = Some(number);
     }
 
-    last_number_spoken.unwrap()
+    fn record(&mut self, number: u64, turn: u64) {
+        if number < self.dense.len() as u64 && turn < u32::MAX as u64 {
+            self.dense[number as usize] = turn as u32 + 1;
+        } else {
+            self.sparse.insert(number, turn);
+        }
+    }
 }
 
-fn main() {
-    let input = std::fs::read_to_string("inputs/day15/input").unwrap();
-    let starting_numbers: Vec<u32> = input
-        .trim()
-        .split(',')
-        .map(|n| n.parse().unwrap())
+impl Iterator for MemoryGame {
+    type Item = u64;
+
+    fn next(&mut self) -> Option<u64> {
+        let turn = self.turn;
+        let number = match self.starting_numbers.get(turn as usize) {
+            Some(&number) => number,
+            None => {
+                let last_number_spoken = self.last_number_spoken?;
+                self.last_spoken_in_turn(last_number_spoken)
+                    .map(|n| turn - n - 1)
+                    .unwrap_or(0)
+            }
+        };
+
+        if let Some(last_number_spoken) = self.last_number_spoken {
+            self.record(last_number_spoken, turn - 1);
+        }
+        self.last_number_spoken = Some(number);
+        self.turn += 1;
+        Some(number)
+    }
+}
+
+/// Returns the numbers spoken in every turn of `turns`, counting from one, in the same order, by
+/// playing the game once up to the last of them.
+fn numbers_at(starting_numbers: &[u64], turns: &[u64], dense_limit: u64) -> Vec<u64> {
+    let mut order: Vec<usize> = (0..turns.len()).collect();
+    order.sort_by_key(|&i| turns[i]);
+    let mut game = MemoryGame::new(starting_numbers, dense_limit);
+    let mut numbers = vec![0; turns.len()];
+    let mut spoken = None;
+    let mut played = 0;
+    for i in order {
+        if turns[i] > played {
+            spoken = game.nth((turns[i] - played - 1) as usize);
+            played = turns[i];
+        }
+        numbers[i] = spoken.expect("turns count from one");
+    }
+    numbers
+}
+
+/// A batch in which not even a single game fits in the memory.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+struct OutOfMemory {
+    needed: u64,
+    available: u64,
+}
+
+impl fmt::Display for OutOfMemory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "a game takes up to {}MB, more than the {}MB available",
+            (self.needed + (1 << 20) - 1) >> 20,
+            self.available >> 20
+        )
+    }
+}
+
+/// Plays a game for every sequence of starting numbers on at most `threads` threads, of which
+/// every one plays a single game at a time. Runs fewer threads if their games could take more
+/// than `memory` bytes together. Returns the numbers in the order of the sequences.
+fn batch(
+    sequences: &[Vec<u64>],
+    turns: &[u64],
+    threads: usize,
+    memory: u64,
+) -> Result<Vec<Vec<u64>>, OutOfMemory> {
+    let last_turn = turns.iter().copied().max().unwrap_or(0);
+    // Every number spoken before the last turn is smaller than it
+    let dense_limit = last_turn.min(DENSE_LIMIT);
+    let longest = sequences.iter().map(Vec::len).max().unwrap_or(0);
+    let needed = MemoryGame::memory(longest, last_turn, dense_limit);
+    if needed > memory {
+        return Err(OutOfMemory {
+            needed,
+            available: memory,
+        });
+    }
+    let threads = threads
+        .min((memory / needed.max(1)) as usize)
+        .min(sequences.len());
+
+    let sequences = Arc::new(sequences.to_vec());
+    let turns = Arc::new(turns.to_vec());
+    let next = Arc::new(AtomicUsize::new(0));
+    let (sender, receiver) = mpsc::channel();
+    let workers: Vec<_> = (0..threads)
+        .map(|_| {
+            let sequences = sequences.clone();
+            let turns = turns.clone();
+            let next = next.clone();
+            let sender = sender.clone();
+            thread::spawn(move || loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                match sequences.get(i) {
+                    Some(sequence) => {
+                        let numbers = numbers_at(sequence, &turns, dense_limit);
+                        sender.send((i, numbers)).unwrap();
+                    }
+                    None => break,
+                }
+            })
+        })
         .collect();
-    println!("Solution 1: {}", count(2020, &starting_numbers));
-    println!("Solution 2: {}", count(30000000, &starting_numbers));
+    drop(sender);
+
+    let mut results = vec![Vec::new(); sequences.len()];
+    for (i, numbers) in receiver {
+        results[i] = numbers;
+    }
+    for worker in workers {
+        worker.join().unwrap();
+    }
+    Ok(results)
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+struct ParseError {
+    line: usize,
+    text: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "line {}: expected comma separated numbers, found '{}'",
+            self.line, self.text
+        )
+    }
+}
+
+/// Parses one sequence of starting numbers per line, skipping empty lines.
+fn parse(input: &str) -> Result<Vec<Vec<u64>>, Vec<ParseError>> {
+    let mut sequences = Vec::new();
+    let mut errors = Vec::new();
+    for (i, line) in input.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        match line.split(',').map(|n| n.trim().parse()).collect() {
+            Ok(sequence) => sequences.push(sequence),
+            Err(_) => errors.push(ParseError {
+                line: i + 1,
+                text: line.to_owned(),
+            }),
+        }
+    }
+    if errors.is_empty() {
+        Ok(sequences)
+    } else {
+        Err(errors)
+    }
+}
+
+/// Parses a list of turns like `2020,30000000`.
+fn parse_turns(turns: &str) -> Option<Vec<u64>> {
+    turns
+        .split(',')
+        .map(|turn| turn.trim().parse().ok().filter(|&turn| turn > 0))
+        .collect()
+}
+
+fn main() {
+    let mut path = "inputs/day15/input".to_owned();
+    let mut batch_path = None;
+    let mut turns = vec![2020, 30000000];
+    let mut threads = num_cpus::get();
+    let mut memory = 4 << 30;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--batch" => batch_path = Some(args.next().expect("--batch requires a file")),
+            "--turns" => {
+                turns = args
+                    .next()
+                    .as_deref()
+                    .and_then(parse_turns)
+                    .expect("--turns requires turns like 2020,30000000")
+            }
+            "--threads" => {
+                threads = args
+                    .next()
+                    .and_then(|n| n.parse().ok())
+                    .filter(|&n| n > 0)
+                    .expect("--threads requires a number")
+            }
+            "--memory" => {
+                memory = args
+                    .next()
+                    .and_then(|n| n.parse::<u64>().ok())
+                    .expect("--memory requires a number of megabytes")
+                    << 20
+            }
+            _ => path = arg,
+        }
+    }
+
+    let batched = batch_path.is_some();
+    let input = std::fs::read_to_string(batch_path.unwrap_or(path)).unwrap();
+    let sequences = match parse(&input) {
+        Ok(sequences) => sequences,
+        Err(errors) => {
+            for error in errors {
+                eprintln!("{}", error);
+            }
+            std::process::exit(1);
+        }
+    };
+
+    let results = match batch(&sequences, &turns, threads, memory) {
+        Ok(results) => results,
+        Err(error) => {
+            eprintln!("{}", error);
+            std::process::exit(1);
+        }
+    };
+    if batched {
+        for (sequence, numbers) in sequences.iter().zip(results) {
+            let join = |numbers: &[u64], separator| {
+                numbers
+                    .iter()
+                    .map(|n| n.to_string())
+                    .collect::<Vec<_>>()
+                    .join(separator)
+            };
+            println!("{}: {}", join(sequence, ","), join(&numbers, " "));
+        }
+    } else if let Some(numbers) = results.first() {
+        for (i, number) in numbers.iter().enumerate() {
+            println!("Solution {}: {}", i + 1, number);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{batch, numbers_at, parse, parse_turns, MemoryGame, OutOfMemory};
+
+    #[test]
+    fn example() {
+        assert_eq!(
+            MemoryGame::new(&[0, 3, 6], 10).take(10).collect::<Vec<_>>(),
+            vec![0, 3, 6, 0, 3, 3, 1, 0, 4, 0]
+        );
+        assert_eq!(numbers_at(&[0, 3, 6], &[2020, 4], 2020), vec![436, 0]);
+        assert_eq!(MemoryGame::new(&[], 10).next(), None);
+    }
+
+    #[test]
+    fn sparse() {
+        let dense = MemoryGame::new(&[1, 20, 8, 12, 0, 14], 100_000).take(100_000);
+        let sparse = MemoryGame::new(&[1, 20, 8, 12, 0, 14], 100).take(100_000);
+        assert!(dense.eq(sparse));
+    }
+
+    #[test]
+    fn batches() {
+        let sequences = parse("1,3,2\n\n2,1,3\n0,3,6\n3,1,2").unwrap();
+        let turns = parse_turns("2020,10,1").unwrap();
+        let expected = vec![
+            vec![1, 7, 1],
+            vec![10, 6, 2],
+            vec![436, 0, 0],
+            vec![1836, 8, 3],
+        ];
+        assert_eq!(batch(&sequences, &turns, 3, 1 << 20), Ok(expected.clone()));
+        // Only enough memory for a single game at a time
+        let needed = 2020 * 4 + 3 * 48;
+        assert_eq!(batch(&sequences, &turns, 3, needed), Ok(expected));
+        assert_eq!(
+            batch(&sequences, &turns, 3, needed - 1),
+            Err(OutOfMemory {
+                needed,
+                available: needed - 1
+            })
+        );
+        assert_eq!(parse("1,2\n3,x").unwrap_err()[0].line, 2);
+        assert_eq!(parse_turns("10,0"), None);
+    }
 }
@@ -1,3 +1,5 @@
+use adventofcode2020::frames::{Cell, Frame, FrameArgs};
+use adventofcode2020::stats::{Stats, StatsArgs};
 use std::collections::{HashMap, HashSet};
 use std::iter::FromIterator;
 
@@ -158,8 +160,64 @@ fn update4(active: &[Point4]) -> Vec<Point4> {
     result
 }
 
+/// Lays out the xy slices of the active points side by side, a column for every z and a row for
+/// every w.
+fn slices_frame(active: &[Point4]) -> Frame {
+    let (min, max) = match (
+        min_point4(active.iter().copied()),
+        max_point4(active.iter().copied()),
+    ) {
+        (Some(min), Some(max)) => (min, max),
+        _ => return Frame::new(0, 0),
+    };
+    let width = (max.0 - min.0 + 1) as usize;
+    let height = (max.1 - min.1 + 1) as usize;
+    let columns = (max.2 - min.2 + 1) as usize;
+    let rows = (max.3 - min.3 + 1) as usize;
+
+    let mut frame = Frame::new(columns * (width + 1) - 1, rows * (height + 1) - 1);
+    let position = |p: Point4| {
+        let column = (p.2 - min.2) as usize;
+        let row = (p.3 - min.3) as usize;
+        (
+            column * (width + 1) + (p.0 - min.0) as usize,
+            row * (height + 1) + (p.1 - min.1) as usize,
+        )
+    };
+    for w in min.3..=max.3 {
+        for z in min.2..=max.2 {
+            for y in min.1..=max.1 {
+                for x in min.0..=max.0 {
+                    let (x, y) = position(Point4(x, y, z, w));
+                    frame.set(x, y, Cell::Dead);
+                }
+            }
+        }
+    }
+    for p in active.iter() {
+        let (x, y) = position(*p);
+        frame.set(x, y, Cell::Alive);
+    }
+    frame
+}
+
 fn main() {
-    let input = std::fs::read_to_string("inputs/day17/input").unwrap();
+    let mut path = "inputs/day17/input".to_owned();
+    let mut frame_args = FrameArgs::default();
+    let mut stats_args = StatsArgs::default();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if !frame_args.parse(&arg, &mut args) && !stats_args.parse(&arg, &mut args) {
+            path = arg;
+        }
+    }
+    let frames = frame_args.writer();
+    let frames3 = frames.as_ref().map(|f| f.subdirectory("3d").unwrap());
+    let frames4 = frames.as_ref().map(|f| f.subdirectory("4d").unwrap());
+    let mut stats3 = stats_args.writer("3d", &["x", "y", "z"]);
+    let mut stats4 = stats_args.writer("4d", &["x", "y", "z", "w"]);
+
+    let input = std::fs::read_to_string(path).unwrap();
     let initial_state: Vec<Point3> = input
         .lines()
         .enumerate()
@@ -174,9 +232,26 @@ fn main() {
         })
         .collect();
 
+    let mut record3 = |cycle, before: &[Point3], after: &[Point3]| {
+        if let Some(frames) = &frames3 {
+            let after: Vec<Point4> = after.iter().map(|p| Point4(p.0, p.1, p.2, 0)).collect();
+            frames.write(cycle, &slices_frame(&after)).unwrap();
+        }
+        if let Some(stats) = &mut stats3 {
+            let before: HashSet<Point3> = before.iter().copied().collect();
+            let after: HashSet<Point3> = after.iter().copied().collect();
+            let coordinates = |p: &Point3| vec![p.0, p.1, p.2];
+            stats
+                .write(&Stats::compare(cycle, &before, &after, coordinates))
+                .unwrap();
+        }
+    };
     let mut state = initial_state.clone();
-    for _cycle in 0..6 {
-        state = update3(&state);
+    record3(0, &state, &state);
+    for cycle in 0..6 {
+        let next = update3(&state);
+        record3(cycle + 1, &state, &next);
+        state = next;
     }
 
     println!("Solution 1: {}", state.len());
@@ -185,9 +260,28 @@ fn main() {
         .iter()
         .map(|p| Point4(p.0, p.1, p.2, 0))
         .collect::<Vec<_>>();
-    for _cycle in 0..6 {
-        state = update4(&state);
+    let mut record4 = |cycle, before: &[Point4], after: &[Point4]| {
+        if let Some(frames) = &frames4 {
+            frames.write(cycle, &slices_frame(after)).unwrap();
+        }
+        if let Some(stats) = &mut stats4 {
+            let before: HashSet<Point4> = before.iter().copied().collect();
+            let after: HashSet<Point4> = after.iter().copied().collect();
+            let coordinates = |p: &Point4| vec![p.0, p.1, p.2, p.3];
+            stats
+                .write(&Stats::compare(cycle, &before, &after, coordinates))
+                .unwrap();
+        }
+    };
+    record4(0, &state, &state);
+    for cycle in 0..6 {
+        let next = update4(&state);
+        record4(cycle + 1, &state, &next);
+        state = next;
     }
 
     println!("Solution 2: {}", state.len());
+    for stats in stats3.into_iter().chain(stats4) {
+        stats.finish().unwrap();
+    }
 }
@@ -1,3 +1,5 @@
+use adventofcode2020::frames::{Cell, Frame, FrameArgs};
+use adventofcode2020::stats::{Stats, StatsArgs};
 use std::collections::{HashMap, HashSet};
 
 #[derive(Copy, Clone, Debug, Default, Hash, Eq, PartialEq)]
@@ -90,8 +92,53 @@ fn parse(input: &str) -> Vec<Point> {
         .collect()
 }
 
+/// Draws the tiles in the bounding box of the black tiles. Every tile is two cells wide and every
+/// other row is offset by one cell, so neighbouring tiles touch like they do on the floor.
+fn hex_frame(black_tiles: &HashSet<Point>) -> Frame {
+    if black_tiles.is_empty() {
+        return Frame::new(0, 0);
+    }
+    let min_x = black_tiles.iter().map(|p| p.x).min().unwrap();
+    let max_x = black_tiles.iter().map(|p| p.x).max().unwrap();
+    let min_y = black_tiles.iter().map(|p| p.y).min().unwrap();
+    let max_y = black_tiles.iter().map(|p| p.y).max().unwrap();
+
+    let mut frame = Frame::new(
+        2 * (max_x - min_x) as usize + 3,
+        (max_y - min_y) as usize + 1,
+    );
+    // East decreases x, so x is mirrored to draw east on the right
+    for y in min_y..=max_y {
+        for x in min_x..=max_x {
+            let point = Point { x, y };
+            let column = 2 * (max_x - x) as usize + (y.rem_euclid(2) == 0) as usize;
+            let row = (y - min_y) as usize;
+            let cell = if black_tiles.contains(&point) {
+                Cell::Alive
+            } else {
+                Cell::Dead
+            };
+            frame.set(column, row, cell);
+            frame.set(column + 1, row, cell);
+        }
+    }
+    frame
+}
+
 fn main() {
-    let input = std::fs::read_to_string("inputs/day24/input").unwrap();
+    let mut path = "inputs/day24/input".to_owned();
+    let mut frame_args = FrameArgs::default();
+    let mut stats_args = StatsArgs::default();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if !frame_args.parse(&arg, &mut args) && !stats_args.parse(&arg, &mut args) {
+            path = arg;
+        }
+    }
+    let frames = frame_args.writer();
+    let mut stats = stats_args.writer("tiles", &["x", "y"]);
+
+    let input = std::fs::read_to_string(path).unwrap();
     let input = parse(&input);
 
     let mut black_tiles = HashSet::new();
@@ -104,7 +151,18 @@ fn main() {
     }
     println!("Solution 1: {}", black_tiles.len());
 
-    for _day in 0..100 {
+    let mut record = |day, before: &HashSet<Point>, after: &HashSet<Point>| {
+        if let Some(frames) = &frames {
+            frames.write(day, &hex_frame(after)).unwrap();
+        }
+        if let Some(stats) = &mut stats {
+            stats
+                .write(&Stats::compare(day, before, after, |p| vec![p.x, p.y]))
+                .unwrap();
+        }
+    };
+    record(0, &black_tiles, &black_tiles);
+    for day in 0..100 {
         let mut number_of_black_neighbours = HashMap::new();
         for black_point in black_tiles.iter() {
             for neighbor in &black_point.neighbours() {
@@ -124,7 +182,11 @@ fn main() {
             }
         }
 
+        record(day + 1, &black_tiles, &new_black_tiles);
         black_tiles = new_black_tiles;
     }
     println!("Solution 2: {}", black_tiles.len());
+    if let Some(stats) = stats {
+        stats.finish().unwrap();
+    }
 }
@@ -1,5 +1,7 @@
 use adventofcode2020::regex;
+use std::collections::hash_map::Entry;
 use std::collections::{HashMap, VecDeque};
+use std::fmt;
 use std::iter::FromIterator;
 
 #[derive(Default)]
@@ -27,58 +29,268 @@ impl BagGraph {
         self.id_to_contained_rules.push(Default::default());
         id
     }
+
+    /// Builds the graph from the parsed rules, reporting every duplicate definition, reference to
+    /// an undefined bag and cycle in the rule set.
+    fn build(rules: Vec<RawBagRule>) -> Result<BagGraph, Vec<RuleError>> {
+        let mut graph = BagGraph::default();
+        let mut errors = Vec::new();
+
+        // Insert all nodes
+        let mut defined_on_line = HashMap::new();
+        for rule in rules.iter() {
+            match defined_on_line.entry(rule.bag.as_str()) {
+                Entry::Occupied(first) => errors.push(RuleError::DuplicateDefinition {
+                    bag: rule.bag.clone(),
+                    first_line: *first.get(),
+                    line: rule.line,
+                }),
+                Entry::Vacant(entry) => {
+                    entry.insert(rule.line);
+                    graph.allocate_bag_id(&rule.bag);
+                }
+            }
+        }
+
+        // Create all edges in the graph
+        for rule in rules.iter() {
+            if defined_on_line[rule.bag.as_str()] != rule.line {
+                continue;
+            }
+            let bag = graph.bag_to_id[&rule.bag];
+            for (contains, count) in rule.contains.iter() {
+                let contained_bag = match graph.bag_to_id.get(contains) {
+                    Some(id) => *id,
+                    None => {
+                        errors.push(RuleError::UndefinedBag {
+                            bag: contains.clone(),
+                            line: rule.line,
+                        });
+                        continue;
+                    }
+                };
+                let rule_id = graph.rules.len();
+                graph.rules.push(BagRule {
+                    bag,
+                    count: *count,
+                    contains: contained_bag,
+                });
+                graph.id_to_contained_rules[contained_bag].push(rule_id);
+                graph.id_to_contains_rules[bag].push(rule_id);
+            }
+        }
+
+        errors.extend(graph.find_cycles().into_iter().map(|cycle| {
+            RuleError::Cycle(
+                cycle
+                    .into_iter()
+                    .map(|id| graph.id_to_bag[id].clone())
+                    .collect(),
+            )
+        }));
+
+        if errors.is_empty() {
+            Ok(graph)
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Returns every cycle closed by a back edge of a depth-first traversal. Each cycle starts and
+    /// ends with the same bag.
+    fn find_cycles(&self) -> Vec<Vec<usize>> {
+        #[derive(Copy, Clone, Eq, PartialEq)]
+        enum Visit {
+            Unvisited,
+            OnStack,
+            Done,
+        }
+
+        let mut visits = vec![Visit::Unvisited; self.id_to_bag.len()];
+        let mut cycles = Vec::new();
+        for root in 0..self.id_to_bag.len() {
+            if visits[root] != Visit::Unvisited {
+                continue;
+            }
+
+            // Iterative to stay clear of the stack on deep rule sets
+            let mut stack = vec![(root, 0)];
+            visits[root] = Visit::OnStack;
+            while let Some((bag, next_rule)) = stack.last_mut() {
+                let bag = *bag;
+                if let Some(rule_id) = self.id_to_contains_rules[bag].get(*next_rule) {
+                    *next_rule += 1;
+                    let contains = self.rules[*rule_id].contains;
+                    match visits[contains] {
+                        Visit::Unvisited => {
+                            visits[contains] = Visit::OnStack;
+                            stack.push((contains, 0));
+                        }
+                        Visit::OnStack => {
+                            let start = stack.iter().position(|(b, _)| *b == contains).unwrap();
+                            let mut cycle: Vec<usize> =
+                                stack[start..].iter().map(|(b, _)| *b).collect();
+                            cycle.push(contains);
+                            cycles.push(cycle);
+                        }
+                        Visit::Done => {}
+                    }
+                } else {
+                    visits[bag] = Visit::Done;
+                    stack.pop();
+                }
+            }
+        }
+        cycles
+    }
 }
 
 #[derive(Default)]
 struct RawBagRule {
+    line: usize,
     bag: String,
     contains: Vec<(String, usize)>,
 }
 
-fn parse_rule(input: &str) -> RawBagRule {
-    let captures = regex!("^(.*) bags contain (.*).$").captures(input).unwrap();
-    let bag = captures[1].to_owned();
-    let contains = regex!("(\\d) (.*?) bag[s]?")
-        .captures_iter(&captures[2])
-        .map(|c| (c[2].to_owned(), c[1].parse().unwrap()))
-        .collect();
-    RawBagRule { bag, contains }
+#[derive(Debug, Eq, PartialEq)]
+enum RuleError {
+    Malformed {
+        line: usize,
+        text: String,
+    },
+    InvalidCount {
+        line: usize,
+        count: String,
+    },
+    DuplicateDefinition {
+        bag: String,
+        first_line: usize,
+        line: usize,
+    },
+    UndefinedBag {
+        bag: String,
+        line: usize,
+    },
+    Cycle(Vec<String>),
 }
 
-fn parse(input: &str) -> Vec<RawBagRule> {
-    input.lines().map(parse_rule).collect()
+impl fmt::Display for RuleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RuleError::Malformed { line, text } => {
+                write!(f, "line {}: malformed rule: '{}'", line, text)
+            }
+            RuleError::InvalidCount { line, count } => {
+                write!(f, "line {}: invalid bag count '{}'", line, count)
+            }
+            RuleError::DuplicateDefinition {
+                bag,
+                first_line,
+                line,
+            } => write!(
+                f,
+                "line {}: '{}' bags are already defined on line {}",
+                line, bag, first_line
+            ),
+            RuleError::UndefinedBag { bag, line } => {
+                write!(f, "line {}: '{}' bags are never defined", line, bag)
+            }
+            RuleError::Cycle(bags) => write!(f, "cycle: {}", bags.join(" -> ")),
+        }
+    }
 }
 
-fn main() {
-    let input = std::fs::read_to_string("inputs/day7/input").unwrap();
-    let rules = parse(&input);
+fn parse_rule(line: usize, input: &str) -> Result<RawBagRule, RuleError> {
+    let malformed = || RuleError::Malformed {
+        line,
+        text: input.to_owned(),
+    };
+    let captures = regex!("^(.*) bags contain (.*)\\.$")
+        .captures(input)
+        .ok_or_else(malformed)?;
+    let bag = captures[1].to_owned();
+    if &captures[2] == "no other bags" {
+        return Ok(RawBagRule {
+            line,
+            bag,
+            contains: Vec::new(),
+        });
+    }
 
-    let mut graph = BagGraph::default();
+    let content_regex = regex!("^(\\d+) (.*?) bags?$");
+    let mut contains = Vec::new();
+    for content in captures[2].split(", ") {
+        let c = content_regex.captures(content).ok_or_else(malformed)?;
+        let count = match c[1].parse() {
+            Ok(count) if count > 0 => count,
+            _ => {
+                return Err(RuleError::InvalidCount {
+                    line,
+                    count: c[1].to_owned(),
+                })
+            }
+        };
+        contains.push((c[2].to_owned(), count));
+    }
+    Ok(RawBagRule {
+        line,
+        bag,
+        contains,
+    })
+}
 
-    // Insert all nodes
-    let my_bag = graph.allocate_bag_id("shiny gold");
-    for rule in rules.iter() {
-        if !graph.bag_to_id.contains_key(&rule.bag) {
-            graph.allocate_bag_id(&rule.bag);
+fn parse(input: &str) -> Result<Vec<RawBagRule>, Vec<RuleError>> {
+    let mut rules = Vec::new();
+    let mut errors = Vec::new();
+    for (i, line) in input.lines().enumerate() {
+        match parse_rule(i + 1, line) {
+            Ok(rule) => rules.push(rule),
+            Err(e) => errors.push(e),
         }
     }
+    if errors.is_empty() {
+        Ok(rules)
+    } else {
+        Err(errors)
+    }
+}
 
-    // Create all edges in the graph
-    for rule in rules {
-        let bag = *graph.bag_to_id.get(&rule.bag).unwrap();
-        for (contains, count) in rule.contains {
-            let contained_bag = *graph.bag_to_id.get(&contains).unwrap();
-            let rule_id = graph.rules.len();
-            graph.rules.push(BagRule {
-                bag,
-                count,
-                contains: contained_bag,
-            });
-            graph.id_to_contained_rules[contained_bag].push(rule_id);
-            graph.id_to_contains_rules[bag].push(rule_id);
-        }
+fn compute_contained_bag_count(
+    graph: &BagGraph,
+    bag: usize,
+    bag_count_cache: &mut Vec<Option<usize>>,
+) -> usize {
+    if let Some(bag_count) = bag_count_cache[bag] {
+        return bag_count;
     }
 
+    let mut count = 0;
+    for rule_id in graph.id_to_contains_rules[bag].iter() {
+        let rule = &graph.rules[*rule_id];
+        count +=
+            (1 + compute_contained_bag_count(graph, rule.contains, bag_count_cache)) * rule.count;
+    }
+    bag_count_cache[bag] = Some(count);
+    count
+}
+
+fn main() {
+    let input = std::fs::read_to_string("inputs/day7/input").unwrap();
+    let graph = match parse(&input).and_then(BagGraph::build) {
+        Ok(graph) => graph,
+        Err(errors) => {
+            for error in errors {
+                eprintln!("{}", error);
+            }
+            std::process::exit(1);
+        }
+    };
+
+    let my_bag = *graph
+        .bag_to_id
+        .get("shiny gold")
+        .expect("no rule for shiny gold bags");
+
     let mut contains_my_bag = vec![false; graph.id_to_bag.len()];
     let mut stack = VecDeque::from_iter(graph.id_to_contained_rules[my_bag].iter().copied());
 
@@ -100,24 +312,81 @@ fn main() {
 
     let total_count =
         compute_contained_bag_count(&graph, my_bag, &mut vec![None; graph.id_to_bag.len()]);
-    fn compute_contained_bag_count(
-        graph: &BagGraph,
-        bag: usize,
-        bag_count_cache: &mut Vec<Option<usize>>,
-    ) -> usize {
-        if let Some(bag_count) = bag_count_cache[bag] {
-            return bag_count;
-        }
 
-        let mut count = 0;
-        for rule_id in graph.id_to_contains_rules[bag].iter() {
-            let rule = &graph.rules[*rule_id];
-            count += (1 + compute_contained_bag_count(graph, rule.contains, bag_count_cache))
-                * rule.count;
-        }
-        bag_count_cache[bag] = Some(count);
-        count
+    println!("Solution 2: {}", total_count);
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{compute_contained_bag_count, parse, BagGraph, RuleError};
+
+    fn build(input: &str) -> Result<BagGraph, Vec<RuleError>> {
+        parse(input).and_then(BagGraph::build)
     }
 
-    println!("Solution 2: {}", total_count);
+    #[test]
+    fn multi_digit_counts() {
+        let graph = build(
+            "shiny gold bags contain 12 dark red bags.\n\
+             dark red bags contain no other bags.",
+        )
+        .unwrap();
+        let my_bag = graph.bag_to_id["shiny gold"];
+        assert_eq!(
+            compute_contained_bag_count(&graph, my_bag, &mut vec![None; 2]),
+            12
+        );
+    }
+
+    #[test]
+    fn cycle() {
+        let errors = build(
+            "shiny gold bags contain 1 dark red bag.\n\
+             dark red bags contain 2 dim blue bags.\n\
+             dim blue bags contain 1 shiny gold bag.",
+        )
+        .err()
+        .unwrap();
+        assert_eq!(
+            errors,
+            vec![RuleError::Cycle(vec![
+                "shiny gold".to_owned(),
+                "dark red".to_owned(),
+                "dim blue".to_owned(),
+                "shiny gold".to_owned()
+            ])]
+        );
+    }
+
+    #[test]
+    fn duplicate_and_undefined() {
+        let errors = build(
+            "shiny gold bags contain 1 dark red bag.\n\
+             shiny gold bags contain no other bags.",
+        )
+        .err()
+        .unwrap();
+        assert_eq!(
+            errors,
+            vec![
+                RuleError::DuplicateDefinition {
+                    bag: "shiny gold".to_owned(),
+                    first_line: 1,
+                    line: 2
+                },
+                RuleError::UndefinedBag {
+                    bag: "dark red".to_owned(),
+                    line: 1
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn malformed() {
+        let errors = build("shiny gold bags contain ten dark red bags.")
+            .err()
+            .unwrap();
+        assert!(matches!(errors[0], RuleError::Malformed { line: 1, .. }));
+    }
 }
@@ -1,92 +1,180 @@
-#[derive(Debug, Copy, Clone)]
-enum Op {
-    Acc(isize),
-    Jmp(isize),
-    Nop(isize),
-}
+use adventofcode2020::handheld::debugger::{Command, Debugger, Stop, HELP};
+use adventofcode2020::handheld::repair::{repair, repair_minimal};
+use adventofcode2020::handheld::trace::Trace;
+use adventofcode2020::handheld::{assemble, disassemble, InstructionSet, Machine, Op, RunResult};
+use std::io::{BufRead, Write};
 
-#[derive(Default)]
-struct State {
-    cursor: isize,
-    accumulator: isize,
+fn describe(stop: Stop) -> String {
+    match stop {
+        Stop::Breakpoint(index) => format!("breakpoint {} hit", index),
+        Stop::Watchpoint {
+            index,
+            before,
+            after,
+        } => format!(
+            "watchpoint {}: accumulator changed from {} to {}",
+            index, before, after
+        ),
+        Stop::Cycle(address) => format!("loop detected: {} is about to run again", address),
+        Stop::Halted => "program halted".to_owned(),
+        Stop::Fault(fault) => format!("fault: {}", fault),
+    }
 }
 
-impl Op {
-    fn execute(&self, state: &mut State) {
-        match self {
-            Op::Acc(value) => {
-                state.accumulator += *value;
-                state.cursor += 1;
+fn debug(ops: Vec<Op>) {
+    let mut debugger = Debugger::new(Machine::new(ops));
+    let stdin = std::io::stdin();
+    let mut lines = stdin.lock().lines();
+    loop {
+        print!("(dbg) ");
+        std::io::stdout().flush().unwrap();
+        let line = match lines.next() {
+            Some(line) => line.unwrap(),
+            None => break,
+        };
+        let command = match line.parse() {
+            Ok(command) => command,
+            Err(e) => {
+                println!("{}", e);
+                continue;
             }
-            Op::Jmp(offset) => {
-                state.cursor += *offset;
+        };
+
+        let state = debugger.state();
+        match command {
+            Command::Break(breakpoint) => {
+                println!("breakpoint {}", debugger.add_breakpoint(breakpoint))
             }
-            Op::Nop(_) => {
-                state.cursor += 1;
+            Command::Watch(watchpoint) => {
+                println!("watchpoint {}", debugger.add_watchpoint(watchpoint))
             }
-        };
-    }
+            Command::Clear => debugger.clear(),
+            Command::Step(count) => {
+                for _ in 0..count {
+                    if let Some(stop) = debugger.step() {
+                        println!("{}", describe(stop));
+                        break;
+                    }
+                }
+            }
+            Command::Continue => println!("{}", describe(debugger.resume())),
+            Command::Flip(address) => {
+                let address = address.unwrap_or(state.cursor as usize);
+                match debugger.flip(address) {
+                    Ok(op) => {
+                        println!("{:>5}: {}", address, op);
+                        println!("{}", describe(debugger.resume()));
+                    }
+                    Err(fault) => println!("fault: {}", fault),
+                }
+            }
+            Command::Print => {}
+            Command::List => {
+                let program = debugger.machine().program();
+                let start = state.cursor.saturating_sub(5).max(0) as usize;
+                let end = (state.cursor.saturating_add(6).max(0) as usize).min(program.len());
+                for (address, op) in program.iter().enumerate().take(end).skip(start) {
+                    let marker = if address as isize == state.cursor {
+                        '>'
+                    } else {
+                        ' '
+                    };
+                    println!("{} {:>5}: {}", marker, address, op);
+                }
+            }
+            Command::Reset => debugger.machine_mut().reset(),
+            Command::Help => println!("{}", HELP),
+            Command::Quit => break,
+        }
 
-    fn flip_corruption(&self) -> Op {
-        match self {
-            Op::Jmp(value) => Op::Nop(*value),
-            Op::Nop(value) => Op::Jmp(*value),
-            a => *a,
+        let state = debugger.state();
+        if let Command::Print | Command::Step(_) | Command::Continue | Command::Flip(_) = command {
+            let op = debugger
+                .machine()
+                .address()
+                .map(|a| debugger.machine().program()[a]);
+            match op {
+                Ok(op) => println!(
+                    "cursor: {} ({}), accumulator: {}",
+                    state.cursor, op, state.accumulator
+                ),
+                Err(_) => println!(
+                    "cursor: {}, accumulator: {}",
+                    state.cursor, state.accumulator
+                ),
+            }
         }
     }
 }
 
-fn parse(input: &str) -> Vec<Op> {
-    input
-        .lines()
-        .map(|line| {
-            let mut split = line.split(' ');
-            match split.next() {
-                Some("nop") => Op::Nop(split.next().unwrap().parse().unwrap()),
-                Some("acc") => Op::Acc(split.next().unwrap().parse().unwrap()),
-                Some("jmp") => Op::Jmp(split.next().unwrap().parse().unwrap()),
-                _ => unreachable!(),
+fn main() {
+    let mut path = "inputs/day8/input".to_owned();
+    let mut instruction_set = InstructionSet::Standard;
+    let mut mode = None;
+    let mut trace_path = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--extended" => instruction_set = InstructionSet::Extended,
+            "--debug" | "--disassemble" => mode = Some(arg),
+            "--trace" => trace_path = Some(args.next().expect("--trace requires a file")),
+            "--replay" => {
+                let path = args.next().expect("--replay requires a file");
+                let file = std::io::BufReader::new(std::fs::File::open(&path).unwrap());
+                match Trace::read(file) {
+                    Ok(trace) => trace.replay(std::io::stdout().lock()).unwrap(),
+                    Err(e) => {
+                        eprintln!("{}: {}", path, e);
+                        std::process::exit(1);
+                    }
+                }
+                return;
             }
-        })
-        .collect()
-}
+            _ => path = arg,
+        }
+    }
 
-enum RunResult {
-    Cycle(isize),
-    Completion(isize),
-}
+    let input = std::fs::read_to_string(&path).unwrap();
+    let ops = match assemble(&input, instruction_set) {
+        Ok(ops) => ops,
+        Err(e) => {
+            eprintln!("{}: {}", path, e);
+            std::process::exit(1);
+        }
+    };
 
-fn run(ops: &[Op]) -> RunResult {
-    let mut state = State::default();
-    let mut instruction_executed = vec![false; ops.len()];
-    loop {
-        if instruction_executed[state.cursor as usize] {
-            break RunResult::Cycle(state.accumulator);
+    match mode.as_deref() {
+        Some("--disassemble") => {
+            print!("{}", disassemble(&ops, true));
+            return;
         }
-        instruction_executed[state.cursor as usize] = true;
-        ops[state.cursor as usize].execute(&mut state);
-        if state.cursor as usize == ops.len() {
-            break RunResult::Completion(state.accumulator);
+        Some("--debug") => {
+            debug(ops);
+            return;
         }
+        _ => {}
     }
-}
 
-fn main() {
-    let input = std::fs::read_to_string("inputs/day8/input").unwrap();
-    let mut ops = parse(&input);
+    if let Some(trace_path) = trace_path {
+        let trace = Trace::record(ops.clone());
+        trace
+            .write(std::io::BufWriter::new(
+                std::fs::File::create(trace_path).unwrap(),
+            ))
+            .unwrap();
+    }
 
-    if let RunResult::Cycle(acc) = run(&ops) {
-        println!("Solution 1: {}", acc);
+    let mut machine = Machine::new(ops);
+
+    match machine.run() {
+        Ok(RunResult::Cycle(acc)) => println!("Solution 1: {}", acc),
+        Ok(RunResult::Completion(acc)) => println!("Program terminated: {}", acc),
+        Err(fault) => println!("Fault: {}", fault),
     }
 
-    for i in 0..ops.len() {
-        if let Op::Acc(_) = ops[i] {
-            continue;
-        }
-        ops[i] = ops[i].flip_corruption();
-        if let RunResult::Completion(acc) = run(&ops) {
-            println!("Solution 2: {}", acc)
-        }
-        ops[i] = ops[i].flip_corruption();
+    let repaired = repair(machine.program()).or_else(|| repair_minimal(machine.program()));
+    if let Some(repaired) = repaired {
+        println!("Solution 2: {}", repaired.accumulator);
+        println!("Flipped instructions: {:?}", repaired.flipped);
     }
 }
@@ -1,58 +1,258 @@
-fn parse(input: &str) -> Vec<usize> {
-    input.lines().map(|l| l.parse().unwrap()).collect()
+use std::collections::{HashMap, VecDeque};
+use std::io::BufRead;
+
+/// A number that is not the sum of two of the numbers in the preamble before it.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+struct Invalid {
+    index: usize,
+    number: usize,
 }
 
-fn part1(numbers: &[usize], preamble: usize) -> usize {
-    for i in preamble..numbers.len() {
-        let number = numbers[i];
+/// Validates a stream of numbers against a sliding window of the previous `preamble` numbers,
+/// yielding every invalid number.
+///
+/// The window keeps a count of every value in it, so checking a number takes `O(preamble)` and
+/// moving the window `O(1)`.
+struct Validator<I> {
+    numbers: I,
+    preamble: usize,
+    window: VecDeque<usize>,
+    counts: HashMap<usize, usize>,
+    index: usize,
+}
 
-        let mut found = false;
-        for a in i - preamble..i {
-            for b in a..i {
-                if numbers[a] + numbers[b] == number {
-                    found = true;
-                    break;
-                }
+impl<I: Iterator<Item = usize>> Validator<I> {
+    fn new<J: IntoIterator<IntoIter = I, Item = usize>>(numbers: J, preamble: usize) -> Self {
+        Validator {
+            numbers: numbers.into_iter(),
+            preamble,
+            window: VecDeque::with_capacity(preamble + 1),
+            counts: HashMap::with_capacity(preamble + 1),
+            index: 0,
+        }
+    }
+
+    /// Whether `number` is the sum of two numbers at different positions in the window.
+    fn is_valid(&self, number: usize) -> bool {
+        self.window.iter().any(|&a| match number.checked_sub(a) {
+            Some(b) if b == a => self.counts.get(&b).copied().unwrap_or(0) >= 2,
+            Some(b) => self.counts.contains_key(&b),
+            None => false,
+        })
+    }
+
+    fn push(&mut self, number: usize) {
+        self.window.push_back(number);
+        *self.counts.entry(number).or_insert(0) += 1;
+        if self.window.len() > self.preamble {
+            let removed = self.window.pop_front().unwrap();
+            let count = self.counts.get_mut(&removed).unwrap();
+            *count -= 1;
+            if *count == 0 {
+                self.counts.remove(&removed);
             }
-            if found {
-                break;
+        }
+    }
+}
+
+impl<I: Iterator<Item = usize>> Iterator for Validator<I> {
+    type Item = Invalid;
+
+    fn next(&mut self) -> Option<Invalid> {
+        loop {
+            let number = self.numbers.next()?;
+            let index = self.index;
+            self.index += 1;
+            let valid = index < self.preamble || self.is_valid(number);
+            self.push(number);
+            if !valid {
+                return Some(Invalid { index, number });
             }
         }
+    }
+}
 
-        if !found {
-            return number;
+/// Iterates over every contiguous range, as inclusive `(start, end)` indices, of at least
+/// `min_len` numbers that sum to `target`, ordered by their end.
+///
+/// Uses two pointers, the numbers are unsigned so the sum only grows when the end moves forward
+/// and only shrinks when the start does. Finding the ranges takes `O(n)` plus the number of
+/// ranges found.
+struct ContiguousRanges<'a> {
+    numbers: &'a [usize],
+    target: usize,
+    min_len: usize,
+    start: usize,
+    end: usize,
+    sum: usize,
+    /// The next start to report for the current end, and the sum from it to the end.
+    candidate: Option<(usize, usize)>,
+}
+
+impl<'a> ContiguousRanges<'a> {
+    fn new(numbers: &'a [usize], target: usize, min_len: usize) -> Self {
+        ContiguousRanges {
+            numbers,
+            target,
+            min_len: min_len.max(1),
+            start: 0,
+            end: 0,
+            sum: 0,
+            candidate: None,
         }
     }
+}
 
-    unreachable!();
+impl<'a> Iterator for ContiguousRanges<'a> {
+    type Item = (usize, usize);
+
+    fn next(&mut self) -> Option<(usize, usize)> {
+        loop {
+            // Ranges ending at the last added number that only differ by leading zeros
+            if let Some((start, sum)) = self.candidate.take() {
+                let end = self.end - 1;
+                if start <= end && sum == self.target && end + 1 - start >= self.min_len {
+                    self.candidate = Some((start + 1, sum - self.numbers[start]));
+                    return Some((start, end));
+                }
+            }
+
+            let number = *self.numbers.get(self.end)?;
+            self.sum += number;
+            self.end += 1;
+            while self.sum > self.target {
+                self.sum -= self.numbers[self.start];
+                self.start += 1;
+            }
+            self.candidate = Some((self.start, self.sum));
+        }
+    }
 }
 
 fn main() {
-    let input = std::fs::read_to_string("inputs/day9/input").unwrap();
-    let numbers = parse(&input);
+    let mut path = "inputs/day9/input".to_owned();
+    let mut preamble = 25;
+    let mut min_len = 2;
+    let mut all_ranges = false;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--preamble" => {
+                preamble = args
+                    .next()
+                    .and_then(|n| n.parse().ok())
+                    .expect("--preamble requires a number")
+            }
+            "--min-length" => {
+                min_len = args
+                    .next()
+                    .and_then(|n| n.parse().ok())
+                    .expect("--min-length requires a number")
+            }
+            "--all-ranges" => all_ranges = true,
+            _ => path = arg,
+        }
+    }
+
+    let file = std::io::BufReader::new(std::fs::File::open(&path).unwrap());
+    let mut numbers = Vec::new();
+    let mut first_invalid = None;
+    let validator = Validator::new(
+        file.lines()
+            .map(|line| line.unwrap().trim().parse().unwrap())
+            .inspect(|number| numbers.push(*number)),
+        preamble,
+    );
+    for Invalid { index, number } in validator {
+        println!("Invalid number at {}: {}", index, number);
+        first_invalid.get_or_insert(number);
+    }
 
-    let preamble = 25;
-    let invalid_number = part1(&numbers, preamble);
+    let invalid_number = match first_invalid {
+        Some(number) => number,
+        None => {
+            println!("All numbers are valid");
+            return;
+        }
+    };
     println!("Solution 1: {}", invalid_number);
 
-    for start in 0..numbers.len() {
-        let mut acc = numbers[start];
-        let mut end = start + 1;
-        let result = loop {
-            acc += numbers[end];
-            if acc == invalid_number {
-                break Some(end);
-            }
-            if acc > invalid_number {
-                break None;
-            };
-            end += 1;
-        };
-        if let Some(result) = result {
-            let smallest = numbers[start..=result].iter().min().unwrap();
-            let largest = numbers[start..=result].iter().max().unwrap();
-            println!("Solution 2: {}", smallest + largest);
-            break;
+    let mut ranges = ContiguousRanges::new(&numbers, invalid_number, min_len);
+    match ranges.next() {
+        Some((start, end)) => {
+            let smallest = numbers[start..=end].iter().min().unwrap();
+            let largest = numbers[start..=end].iter().max().unwrap();
+            println!(
+                "Solution 2: {} (range {}..={})",
+                smallest + largest,
+                start,
+                end
+            );
         }
+        None => println!(
+            "No range of at least {} numbers sums to {}",
+            min_len, invalid_number
+        ),
+    }
+    if all_ranges {
+        for (start, end) in ranges {
+            println!("Range {}..={}", start, end);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{ContiguousRanges, Invalid, Validator};
+
+    const EXAMPLE: [usize; 20] = [
+        35, 20, 15, 25, 47, 40, 62, 55, 65, 95, 102, 117, 150, 182, 127, 219, 299, 277, 309, 576,
+    ];
+
+    #[test]
+    fn example() {
+        assert_eq!(
+            Validator::new(EXAMPLE.iter().copied(), 5).collect::<Vec<_>>(),
+            vec![Invalid {
+                index: 14,
+                number: 127
+            }]
+        );
+    }
+
+    #[test]
+    fn pair_must_use_different_positions() {
+        assert_eq!(
+            Validator::new(vec![3, 3, 6, 3, 6], 2).collect::<Vec<_>>(),
+            vec![
+                Invalid {
+                    index: 3,
+                    number: 3
+                },
+                Invalid {
+                    index: 4,
+                    number: 6
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn contiguous_range() {
+        assert_eq!(ContiguousRanges::new(&EXAMPLE, 127, 2).next(), Some((2, 5)));
+        assert_eq!(ContiguousRanges::new(&EXAMPLE, 127, 5).next(), None);
+    }
+
+    #[test]
+    fn all_ranges() {
+        let numbers = [1, 0, 2, 3, 0, 0, 3, 7];
+        assert_eq!(
+            ContiguousRanges::new(&numbers, 3, 2).collect::<Vec<_>>(),
+            vec![(0, 2), (3, 4), (3, 5), (4, 6), (5, 6)]
+        );
+        assert_eq!(
+            ContiguousRanges::new(&numbers, 3, 1).collect::<Vec<_>>(),
+            vec![(0, 2), (3, 3), (3, 4), (3, 5), (4, 6), (5, 6), (6, 6)]
+        );
     }
 }
@@ -0,0 +1,241 @@
+//! Exports the generations of the cellular automata (days 11, 17 and 24) as images, one file per
+//! generation, so the rules can be watched at work.
+//!
+//! Every automaton maps its cells onto [`Cell`], which gives all of them the same colours.
+
+use std::fmt;
+use std::fs;
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Cell {
+    /// Not part of the automaton, like floor tiles or the space between slices.
+    Void,
+    Dead,
+    Alive,
+}
+
+impl Cell {
+    fn char(self) -> char {
+        match self {
+            Cell::Void => ' ',
+            Cell::Dead => '.',
+            Cell::Alive => '#',
+        }
+    }
+
+    fn rgb(self) -> [u8; 3] {
+        match self {
+            Cell::Void => [24, 24, 32],
+            Cell::Dead => [96, 104, 120],
+            Cell::Alive => [250, 190, 40],
+        }
+    }
+
+    fn grey(self) -> u8 {
+        match self {
+            Cell::Void => 0,
+            Cell::Dead => 96,
+            Cell::Alive => 255,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Format {
+    /// The cells as characters, one line per row.
+    Text,
+    /// A binary colour image.
+    Ppm,
+    /// A binary greyscale image.
+    Pgm,
+}
+
+impl Format {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Format::Text => "txt",
+            Format::Ppm => "ppm",
+            Format::Pgm => "pgm",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct UnknownFormat(String);
+
+impl fmt::Display for UnknownFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "unknown frame format '{}', expected text, ppm or pgm",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for UnknownFormat {}
+
+impl FromStr for Format {
+    type Err = UnknownFormat;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" | "txt" => Ok(Format::Text),
+            "ppm" => Ok(Format::Ppm),
+            "pgm" => Ok(Format::Pgm),
+            _ => Err(UnknownFormat(s.to_owned())),
+        }
+    }
+}
+
+/// A grid of cells, initially all [`Cell::Void`].
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Frame {
+    width: usize,
+    height: usize,
+    cells: Vec<Cell>,
+}
+
+impl Frame {
+    pub fn new(width: usize, height: usize) -> Self {
+        Frame {
+            width,
+            height,
+            cells: vec![Cell::Void; width * height],
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> Cell {
+        self.cells[y * self.width + x]
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, cell: Cell) {
+        self.cells[y * self.width + x] = cell;
+    }
+
+    pub fn write(&self, format: Format, mut w: impl Write) -> io::Result<()> {
+        match format {
+            Format::Text => {
+                for row in self.cells.chunks(self.width.max(1)) {
+                    let line: String = row.iter().map(|cell| cell.char()).collect();
+                    writeln!(w, "{}", line.trim_end())?;
+                }
+            }
+            Format::Ppm => {
+                write!(w, "P6\n{} {}\n255\n", self.width, self.height)?;
+                for cell in self.cells.iter() {
+                    w.write_all(&cell.rgb())?;
+                }
+            }
+            Format::Pgm => {
+                write!(w, "P5\n{} {}\n255\n", self.width, self.height)?;
+                let pixels: Vec<u8> = self.cells.iter().map(|cell| cell.grey()).collect();
+                w.write_all(&pixels)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Writes every frame to its own numbered file in a directory.
+#[derive(Debug, Clone)]
+pub struct FrameWriter {
+    dir: PathBuf,
+    format: Format,
+}
+
+impl FrameWriter {
+    /// Creates the directory if it does not exist yet.
+    pub fn new(dir: impl Into<PathBuf>, format: Format) -> io::Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        Ok(FrameWriter { dir, format })
+    }
+
+    /// Returns a writer for the subdirectory `name`, for automata that run more than once.
+    pub fn subdirectory(&self, name: &str) -> io::Result<Self> {
+        FrameWriter::new(self.dir.join(name), self.format)
+    }
+
+    pub fn write(&self, generation: usize, frame: &Frame) -> io::Result<()> {
+        let path = self
+            .dir
+            .join(format!("{:05}.{}", generation, self.format.extension()));
+        let mut file = BufWriter::new(fs::File::create(path)?);
+        frame.write(self.format, &mut file)?;
+        file.flush()
+    }
+}
+
+/// The `--frames <dir>` and `--format <format>` arguments of the automata.
+#[derive(Debug, Default, Clone)]
+pub struct FrameArgs {
+    dir: Option<PathBuf>,
+    /// Text if not given.
+    format: Option<Format>,
+}
+
+impl FrameArgs {
+    /// Takes the value of `arg` from `args` if it is one of the frame arguments. Returns whether
+    /// it was.
+    pub fn parse(&mut self, arg: &str, args: &mut impl Iterator<Item = String>) -> bool {
+        match arg {
+            "--frames" => {
+                self.dir = Some(args.next().expect("--frames requires a directory").into())
+            }
+            "--format" => {
+                self.format = Some(
+                    args.next()
+                        .expect("--format requires text, ppm or pgm")
+                        .parse()
+                        .unwrap_or_else(|e| panic!("{}", e)),
+                )
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    /// Returns the writer for the directory, `None` if no frames were requested.
+    pub fn writer(&self) -> Option<FrameWriter> {
+        self.dir
+            .as_ref()
+            .map(|dir| FrameWriter::new(dir, self.format.unwrap_or(Format::Text)).unwrap())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Cell, Format, Frame};
+
+    #[test]
+    fn formats() {
+        let mut frame = Frame::new(3, 2);
+        frame.set(0, 0, Cell::Alive);
+        frame.set(1, 0, Cell::Dead);
+        frame.set(2, 1, Cell::Dead);
+
+        let mut text = Vec::new();
+        frame.write(Format::Text, &mut text).unwrap();
+        assert_eq!(String::from_utf8(text).unwrap(), "#.\n  .\n");
+
+        let mut pgm = Vec::new();
+        frame.write(Format::Pgm, &mut pgm).unwrap();
+        assert_eq!(pgm, b"P5\n3 2\n255\n\xff\x60\x00\x00\x00\x60");
+
+        let mut ppm = Vec::new();
+        frame.write(Format::Ppm, &mut ppm).unwrap();
+        assert_eq!(ppm.len(), "P6\n3 2\n255\n".len() + 3 * 6);
+    }
+}
@@ -0,0 +1,462 @@
+//! Virtual machine for the boot code of the handheld game console (day 8).
+
+pub mod asm;
+pub mod debugger;
+pub mod repair;
+pub mod trace;
+
+pub use asm::{assemble, disassemble, AsmError, InstructionSet};
+
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Op {
+    Acc(isize),
+    Jmp(isize),
+    Nop(isize),
+    /// Multiplies the accumulator. Part of the extended instruction set.
+    Mul(isize),
+    /// Jumps if the accumulator is zero. Part of the extended instruction set.
+    Jz(isize),
+    /// Jumps if the accumulator is not zero. Part of the extended instruction set.
+    Jnz(isize),
+    /// Ends the program. Part of the extended instruction set.
+    Hlt,
+}
+
+impl Op {
+    pub fn opcode(&self) -> Opcode {
+        match self {
+            Op::Acc(_) => Opcode::Acc,
+            Op::Jmp(_) => Opcode::Jmp,
+            Op::Nop(_) => Opcode::Nop,
+            Op::Mul(_) => Opcode::Mul,
+            Op::Jz(_) => Opcode::Jz,
+            Op::Jnz(_) => Opcode::Jnz,
+            Op::Hlt => Opcode::Hlt,
+        }
+    }
+
+    pub fn argument(&self) -> Option<isize> {
+        match self {
+            Op::Acc(value)
+            | Op::Jmp(value)
+            | Op::Nop(value)
+            | Op::Mul(value)
+            | Op::Jz(value)
+            | Op::Jnz(value) => Some(*value),
+            Op::Hlt => None,
+        }
+    }
+
+    /// Swaps a `jmp` for a `nop` and vice versa. Other operations are returned as is.
+    pub fn flip_corruption(&self) -> Op {
+        match self {
+            Op::Jmp(value) => Op::Nop(*value),
+            Op::Nop(value) => Op::Jmp(*value),
+            a => *a,
+        }
+    }
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.argument() {
+            Some(argument) => write!(f, "{} {:+}", self.opcode(), argument),
+            None => write!(f, "{}", self.opcode()),
+        }
+    }
+}
+
+impl FromStr for Op {
+    type Err = String;
+
+    /// Parses a single instruction with a numeric argument, as written by [`fmt::Display`].
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut words = s.split_whitespace();
+        let opcode: Opcode = words.next().unwrap_or("").parse()?;
+        let argument = match (opcode, words.next()) {
+            (Opcode::Hlt, None) => 0,
+            (Opcode::Hlt, Some(arg)) => return Err(format!("unexpected argument '{}'", arg)),
+            (_, None) => return Err(format!("'{}' requires an argument", opcode)),
+            (_, Some(arg)) => arg
+                .parse()
+                .map_err(|_| format!("invalid argument '{}'", arg))?,
+        };
+        if let Some(arg) = words.next() {
+            return Err(format!("unexpected argument '{}'", arg));
+        }
+        Ok(opcode.with_argument(argument))
+    }
+}
+
+/// The kind of an [`Op`], without its argument.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Opcode {
+    Acc,
+    Jmp,
+    Nop,
+    Mul,
+    Jz,
+    Jnz,
+    Hlt,
+}
+
+impl Opcode {
+    pub fn mnemonic(self) -> &'static str {
+        match self {
+            Opcode::Acc => "acc",
+            Opcode::Jmp => "jmp",
+            Opcode::Nop => "nop",
+            Opcode::Mul => "mul",
+            Opcode::Jz => "jz",
+            Opcode::Jnz => "jnz",
+            Opcode::Hlt => "hlt",
+        }
+    }
+
+    /// Returns the smallest instruction set that contains this opcode.
+    pub fn instruction_set(self) -> InstructionSet {
+        match self {
+            Opcode::Acc | Opcode::Jmp | Opcode::Nop => InstructionSet::Standard,
+            Opcode::Mul | Opcode::Jz | Opcode::Jnz | Opcode::Hlt => InstructionSet::Extended,
+        }
+    }
+
+    /// Creates the operation with the given argument. The argument is ignored for `hlt`.
+    pub fn with_argument(self, argument: isize) -> Op {
+        match self {
+            Opcode::Acc => Op::Acc(argument),
+            Opcode::Jmp => Op::Jmp(argument),
+            Opcode::Nop => Op::Nop(argument),
+            Opcode::Mul => Op::Mul(argument),
+            Opcode::Jz => Op::Jz(argument),
+            Opcode::Jnz => Op::Jnz(argument),
+            Opcode::Hlt => Op::Hlt,
+        }
+    }
+
+    /// Whether the argument of the opcode is a relative jump offset.
+    pub fn is_jump(self) -> bool {
+        matches!(self, Opcode::Jmp | Opcode::Jz | Opcode::Jnz)
+    }
+}
+
+impl fmt::Display for Opcode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.mnemonic())
+    }
+}
+
+impl FromStr for Opcode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "acc" => Ok(Opcode::Acc),
+            "jmp" => Ok(Opcode::Jmp),
+            "nop" => Ok(Opcode::Nop),
+            "mul" => Ok(Opcode::Mul),
+            "jz" => Ok(Opcode::Jz),
+            "jnz" => Ok(Opcode::Jnz),
+            "hlt" => Ok(Opcode::Hlt),
+            _ => Err(format!("unknown opcode '{}'", s)),
+        }
+    }
+}
+
+/// Parses a program written in the standard instruction set.
+pub fn parse(input: &str) -> Result<Vec<Op>, AsmError> {
+    assemble(input, InstructionSet::Standard)
+}
+
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub struct State {
+    pub cursor: isize,
+    pub accumulator: isize,
+}
+
+/// An error raised when the cursor leaves the program other than by stepping exactly one past the
+/// last instruction.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Fault {
+    /// The machine was asked to execute at an address outside of the program.
+    InvalidCursor(isize),
+    /// The instruction at `address` moved the cursor to `target`, outside of the program. Targets
+    /// that do not fit in an `isize` are clamped.
+    JumpOutOfBounds { address: usize, target: isize },
+    /// The `acc` or `mul` instruction at `address` overflowed the accumulator.
+    Overflow { address: usize },
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Fault::InvalidCursor(cursor) => write!(f, "cursor {} is outside the program", cursor),
+            Fault::JumpOutOfBounds { address, target } => write!(
+                f,
+                "instruction at {} jumped to {}, outside the program",
+                address, target
+            ),
+            Fault::Overflow { address } => {
+                write!(f, "instruction at {} overflowed the accumulator", address)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Fault {}
+
+/// Passed to the execution hooks of a [`Machine`] after every executed instruction.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Execution {
+    pub address: usize,
+    pub op: Op,
+    pub before: State,
+    pub after: State,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Status {
+    Running,
+    /// The cursor points directly past the last instruction.
+    Halted,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Exit {
+    Halted,
+    /// The condition passed to [`Machine::run_until`] held before the next instruction.
+    Stopped,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum RunResult {
+    /// An instruction was about to be executed for the second time. Holds the accumulator.
+    Cycle(isize),
+    /// The program halted. Holds the accumulator.
+    Completion(isize),
+}
+
+type Hook<'a> = Box<dyn FnMut(&Execution) + 'a>;
+
+pub struct Machine<'a> {
+    program: Vec<Op>,
+    state: State,
+    hooks: Vec<Hook<'a>>,
+}
+
+impl<'a> Machine<'a> {
+    pub fn new(program: Vec<Op>) -> Self {
+        Machine {
+            program,
+            state: State::default(),
+            hooks: Vec::new(),
+        }
+    }
+
+    pub fn program(&self) -> &[Op] {
+        &self.program
+    }
+
+    pub fn program_mut(&mut self) -> &mut [Op] {
+        &mut self.program
+    }
+
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    pub fn state_mut(&mut self) -> &mut State {
+        &mut self.state
+    }
+
+    /// Resets the cursor and accumulator, keeping the program and the hooks.
+    pub fn reset(&mut self) {
+        self.state = State::default();
+    }
+
+    /// Registers a hook that is called after every executed instruction.
+    pub fn on_execute(&mut self, hook: impl FnMut(&Execution) + 'a) {
+        self.hooks.push(Box::new(hook));
+    }
+
+    pub fn status(&self) -> Status {
+        if self.state.cursor == self.program.len() as isize {
+            Status::Halted
+        } else {
+            Status::Running
+        }
+    }
+
+    /// Executes the instruction under the cursor. Does nothing if the machine already halted.
+    pub fn step(&mut self) -> Result<Status, Fault> {
+        if self.status() == Status::Halted {
+            return Ok(Status::Halted);
+        }
+        let address = self.address()?;
+        let op = self.program[address];
+        let before = self.state;
+        let jump = |offset: isize| {
+            before
+                .cursor
+                .checked_add(offset)
+                .ok_or(Fault::JumpOutOfBounds {
+                    address,
+                    target: before.cursor.saturating_add(offset),
+                })
+        };
+        let overflow = Fault::Overflow { address };
+        match op {
+            Op::Acc(value) => {
+                self.state.accumulator = before.accumulator.checked_add(value).ok_or(overflow)?;
+                self.state.cursor += 1;
+            }
+            Op::Jmp(offset) => {
+                self.state.cursor = jump(offset)?;
+            }
+            Op::Nop(_) => {
+                self.state.cursor += 1;
+            }
+            Op::Mul(value) => {
+                self.state.accumulator = before.accumulator.checked_mul(value).ok_or(overflow)?;
+                self.state.cursor += 1;
+            }
+            Op::Jz(offset) => {
+                self.state.cursor = jump(if before.accumulator == 0 { offset } else { 1 })?;
+            }
+            Op::Jnz(offset) => {
+                self.state.cursor = jump(if before.accumulator != 0 { offset } else { 1 })?;
+            }
+            Op::Hlt => {
+                self.state.cursor = self.program.len() as isize;
+            }
+        };
+
+        let execution = Execution {
+            address,
+            op,
+            before,
+            after: self.state,
+        };
+        for hook in self.hooks.iter_mut() {
+            hook(&execution);
+        }
+
+        let status = self.status();
+        if status == Status::Running && self.address().is_err() {
+            return Err(Fault::JumpOutOfBounds {
+                address,
+                target: self.state.cursor,
+            });
+        }
+        Ok(status)
+    }
+
+    /// Steps until the machine halts or `condition` holds before the next instruction.
+    pub fn run_until(&mut self, mut condition: impl FnMut(&Self) -> bool) -> Result<Exit, Fault> {
+        loop {
+            if self.status() == Status::Halted {
+                return Ok(Exit::Halted);
+            }
+            if condition(self) {
+                return Ok(Exit::Stopped);
+            }
+            self.step()?;
+        }
+    }
+
+    /// Runs until the program halts or an instruction is about to be executed a second time.
+    ///
+    /// Programs with conditional jumps may legitimately execute an instruction more than once, use
+    /// [`Machine::run_until`] to run those.
+    pub fn run(&mut self) -> Result<RunResult, Fault> {
+        let mut executed = vec![false; self.program.len()];
+        let exit = self.run_until(|machine| match machine.address() {
+            Ok(address) => std::mem::replace(&mut executed[address], true),
+            Err(_) => false,
+        })?;
+        Ok(match exit {
+            Exit::Halted => RunResult::Completion(self.state.accumulator),
+            Exit::Stopped => RunResult::Cycle(self.state.accumulator),
+        })
+    }
+
+    /// Returns the address under the cursor.
+    pub fn address(&self) -> Result<usize, Fault> {
+        if self.state.cursor >= 0 && self.state.cursor < self.program.len() as isize {
+            Ok(self.state.cursor as usize)
+        } else {
+            Err(Fault::InvalidCursor(self.state.cursor))
+        }
+    }
+}
+
+/// The example program of day 8, which loops unless the instruction at 7 is flipped.
+#[cfg(test)]
+const EXAMPLE: &str = "nop +0\nacc +1\njmp +4\nacc +3\njmp -3\nacc -99\nacc +1\njmp -4\nacc +6";
+
+#[cfg(test)]
+mod test {
+    use super::{assemble, parse, Fault, InstructionSet, Machine, RunResult, EXAMPLE};
+
+    #[test]
+    fn cycle() {
+        let mut machine = Machine::new(parse(EXAMPLE).unwrap());
+        assert_eq!(machine.run(), Ok(RunResult::Cycle(5)));
+    }
+
+    #[test]
+    fn completion() {
+        let mut machine = Machine::new(parse(EXAMPLE).unwrap());
+        machine.program_mut()[7] = machine.program()[7].flip_corruption();
+        assert_eq!(machine.run(), Ok(RunResult::Completion(8)));
+    }
+
+    #[test]
+    fn jump_out_of_bounds() {
+        let mut machine = Machine::new(parse("nop +0\njmp -2").unwrap());
+        assert_eq!(
+            machine.run(),
+            Err(Fault::JumpOutOfBounds {
+                address: 1,
+                target: -1
+            })
+        );
+    }
+
+    #[test]
+    fn overflow() {
+        let mut machine = Machine::new(parse("acc +9223372036854775807\nacc +1").unwrap());
+        assert_eq!(machine.run(), Err(Fault::Overflow { address: 1 }));
+        assert_eq!(machine.state().accumulator, isize::MAX);
+
+        let program = assemble(
+            "acc +3\nmul 4611686018427387904\nhlt",
+            InstructionSet::Extended,
+        );
+        let mut machine = Machine::new(program.unwrap());
+        assert_eq!(machine.run(), Err(Fault::Overflow { address: 1 }));
+
+        let mut machine = Machine::new(parse("nop +0\njmp +9223372036854775807").unwrap());
+        assert_eq!(
+            machine.run(),
+            Err(Fault::JumpOutOfBounds {
+                address: 1,
+                target: isize::MAX
+            })
+        );
+    }
+
+    #[test]
+    fn hooks() {
+        let mut addresses = Vec::new();
+        {
+            let mut machine = Machine::new(parse(EXAMPLE).unwrap());
+            machine.on_execute(|execution| addresses.push(execution.address));
+            machine.run().unwrap();
+        }
+        assert_eq!(addresses, vec![0, 1, 2, 6, 7, 3, 4]);
+    }
+}
@@ -0,0 +1,287 @@
+//! Text format for boot code programs.
+//!
+//! Every line holds at most one instruction, optionally preceded by one or more `label:`
+//! definitions. Everything after a `;` or `#` is a comment. Jump instructions take either a
+//! relative offset or the name of a label.
+//!
+//! ```text
+//! ; count down from three
+//!         acc +3
+//! loop:   acc -1
+//!         jnz loop
+//!         hlt
+//! ```
+
+use super::{Op, Opcode};
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+
+/// Selects which instructions the assembler accepts.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
+pub enum InstructionSet {
+    /// `acc`, `jmp` and `nop`, as understood by the handheld.
+    Standard,
+    /// The standard instructions plus `mul`, `jz`, `jnz` and `hlt`.
+    Extended,
+}
+
+impl InstructionSet {
+    pub fn contains(self, opcode: Opcode) -> bool {
+        opcode.instruction_set() <= self
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum AsmErrorKind {
+    UnknownInstruction(String),
+    /// The instruction exists but is not part of the selected instruction set.
+    UnsupportedInstruction(Opcode),
+    MissingArgument(Opcode),
+    UnexpectedArgument(String),
+    InvalidArgument(String),
+    DuplicateLabel(String),
+    UndefinedLabel(String),
+}
+
+/// An error in the source of a program, with the line number it occurred on.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct AsmError {
+    pub line: usize,
+    pub kind: AsmErrorKind,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: ", self.line)?;
+        match &self.kind {
+            AsmErrorKind::UnknownInstruction(name) => write!(f, "unknown instruction '{}'", name),
+            AsmErrorKind::UnsupportedInstruction(opcode) => {
+                write!(f, "'{}' requires the extended instruction set", opcode)
+            }
+            AsmErrorKind::MissingArgument(opcode) => write!(f, "'{}' requires an argument", opcode),
+            AsmErrorKind::UnexpectedArgument(arg) => write!(f, "unexpected argument '{}'", arg),
+            AsmErrorKind::InvalidArgument(arg) => write!(f, "invalid argument '{}'", arg),
+            AsmErrorKind::DuplicateLabel(label) => {
+                write!(f, "label '{}' is already defined", label)
+            }
+            AsmErrorKind::UndefinedLabel(label) => write!(f, "label '{}' is never defined", label),
+        }
+    }
+}
+
+impl std::error::Error for AsmError {}
+
+enum Argument<'a> {
+    None,
+    Value(isize),
+    Label(&'a str),
+}
+
+struct Instruction<'a> {
+    line: usize,
+    opcode: Opcode,
+    argument: Argument<'a>,
+}
+
+fn is_label(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        _ => false,
+    }
+}
+
+/// Assembles the program, accepting only the instructions of `instruction_set`.
+pub fn assemble(input: &str, instruction_set: InstructionSet) -> Result<Vec<Op>, AsmError> {
+    let mut labels = HashMap::new();
+    let mut instructions = Vec::new();
+
+    for (i, line) in input.lines().enumerate() {
+        let line_number = i + 1;
+        let error = |kind| AsmError {
+            line: line_number,
+            kind,
+        };
+
+        let mut code = line.split(&[';', '#'][..]).next().unwrap().trim();
+        while let Some(colon) = code.find(':') {
+            let label = code[..colon].trim();
+            if !is_label(label) {
+                break;
+            }
+            if labels.insert(label, instructions.len()).is_some() {
+                return Err(error(AsmErrorKind::DuplicateLabel(label.to_owned())));
+            }
+            code = code[colon + 1..].trim();
+        }
+        if code.is_empty() {
+            continue;
+        }
+
+        let mut words = code.split_whitespace();
+        let mnemonic = words.next().unwrap();
+        let opcode: Opcode = mnemonic
+            .parse()
+            .map_err(|_| error(AsmErrorKind::UnknownInstruction(mnemonic.to_owned())))?;
+        if !instruction_set.contains(opcode) {
+            return Err(error(AsmErrorKind::UnsupportedInstruction(opcode)));
+        }
+
+        let argument = match (opcode, words.next()) {
+            (Opcode::Hlt, None) => Argument::None,
+            (Opcode::Hlt, Some(arg)) => {
+                return Err(error(AsmErrorKind::UnexpectedArgument(arg.to_owned())))
+            }
+            (_, None) => return Err(error(AsmErrorKind::MissingArgument(opcode))),
+            (_, Some(arg)) => match arg.parse() {
+                Ok(value) => Argument::Value(value),
+                Err(_) if opcode.is_jump() && is_label(arg) => Argument::Label(arg),
+                Err(_) => return Err(error(AsmErrorKind::InvalidArgument(arg.to_owned()))),
+            },
+        };
+        if let Some(arg) = words.next() {
+            return Err(error(AsmErrorKind::UnexpectedArgument(arg.to_owned())));
+        }
+
+        instructions.push(Instruction {
+            line: line_number,
+            opcode,
+            argument,
+        });
+    }
+
+    instructions
+        .into_iter()
+        .enumerate()
+        .map(|(address, instruction)| {
+            let argument = match instruction.argument {
+                Argument::None => 0,
+                Argument::Value(value) => value,
+                Argument::Label(label) => match labels.get(label) {
+                    Some(target) => *target as isize - address as isize,
+                    None => {
+                        return Err(AsmError {
+                            line: instruction.line,
+                            kind: AsmErrorKind::UndefinedLabel(label.to_owned()),
+                        })
+                    }
+                },
+            };
+            Ok(instruction.opcode.with_argument(argument))
+        })
+        .collect()
+}
+
+/// Writes the program in the text format understood by [`assemble`]. With `labels`, every jump
+/// target inside the program gets a label that the jumps refer to instead of an offset.
+pub fn disassemble(program: &[Op], labels: bool) -> String {
+    let target = |address: usize, op: &Op| -> Option<usize> {
+        let target = address as isize + op.argument()?;
+        if labels && op.opcode().is_jump() && target >= 0 && target <= program.len() as isize {
+            Some(target as usize)
+        } else {
+            None
+        }
+    };
+    let targets: BTreeSet<usize> = program
+        .iter()
+        .enumerate()
+        .filter_map(|(address, op)| target(address, op))
+        .collect();
+
+    let mut result = String::new();
+    for address in 0..=program.len() {
+        if targets.contains(&address) {
+            result.push_str(&format!("l{}:\n", address));
+        }
+        let op = match program.get(address) {
+            Some(op) => op,
+            None => break,
+        };
+        if labels {
+            result.push_str("    ");
+        }
+        match target(address, op) {
+            Some(target) => result.push_str(&format!("{} l{}\n", op.opcode(), target)),
+            None => result.push_str(&format!("{}\n", op)),
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::{assemble, disassemble, AsmError, AsmErrorKind, InstructionSet};
+    use crate::handheld::{Exit, Machine, Op, Opcode};
+
+    const COUNTDOWN: &str = "\
+; count down from three
+        acc +3
+loop:   acc -1  # decrement
+        jnz loop
+        hlt";
+
+    #[test]
+    fn labels_and_comments() {
+        let program = assemble(COUNTDOWN, InstructionSet::Extended).unwrap();
+        assert_eq!(program, vec![Op::Acc(3), Op::Acc(-1), Op::Jnz(-1), Op::Hlt]);
+        let mut machine = Machine::new(program);
+        assert_eq!(machine.run_until(|_| false), Ok(Exit::Halted));
+        assert_eq!(machine.state().accumulator, 0);
+    }
+
+    #[test]
+    fn instruction_set() {
+        assert_eq!(
+            assemble(COUNTDOWN, InstructionSet::Standard),
+            Err(AsmError {
+                line: 4,
+                kind: AsmErrorKind::UnsupportedInstruction(Opcode::Jnz)
+            })
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let error = |input| assemble(input, InstructionSet::Extended).unwrap_err().kind;
+        assert_eq!(
+            error("jmp end"),
+            AsmErrorKind::UndefinedLabel("end".to_owned())
+        );
+        assert_eq!(
+            error("a: nop +0\na: nop +0"),
+            AsmErrorKind::DuplicateLabel("a".to_owned())
+        );
+        assert_eq!(
+            error("add +1"),
+            AsmErrorKind::UnknownInstruction("add".to_owned())
+        );
+        assert_eq!(error("acc"), AsmErrorKind::MissingArgument(Opcode::Acc));
+        assert_eq!(
+            error("acc x"),
+            AsmErrorKind::InvalidArgument("x".to_owned())
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        let program = vec![
+            Op::Nop(0),
+            Op::Acc(1),
+            Op::Jmp(4),
+            Op::Acc(3),
+            Op::Jmp(-3),
+            Op::Jz(-5),
+            Op::Jmp(10),
+        ];
+        for labels in [false, true].iter() {
+            let text = disassemble(&program, *labels);
+            assert_eq!(
+                assemble(&text, InstructionSet::Extended),
+                Ok(program.clone())
+            );
+        }
+    }
+}
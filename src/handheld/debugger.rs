@@ -0,0 +1,299 @@
+//! Breakpoints, watchpoints and single-stepping on top of a [`Machine`].
+
+use super::{Fault, Machine, Op, Opcode, State, Status};
+use std::str::FromStr;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Breakpoint {
+    Address(usize),
+    Opcode(Opcode),
+}
+
+impl Breakpoint {
+    fn matches(&self, address: usize, op: Op) -> bool {
+        match self {
+            Breakpoint::Address(a) => *a == address,
+            Breakpoint::Opcode(opcode) => *opcode == op.opcode(),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Watchpoint {
+    /// Triggers whenever the accumulator changes.
+    Changed,
+    /// Triggers when the accumulator becomes the given value.
+    Equals(isize),
+}
+
+impl Watchpoint {
+    fn matches(&self, before: isize, after: isize) -> bool {
+        match self {
+            Watchpoint::Changed => before != after,
+            Watchpoint::Equals(value) => before != after && after == *value,
+        }
+    }
+}
+
+/// The reason the debugger handed control back to the user.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Stop {
+    /// The breakpoint with the given index matches the next instruction.
+    Breakpoint(usize),
+    /// The watchpoint with the given index triggered on the last instruction.
+    Watchpoint {
+        index: usize,
+        before: isize,
+        after: isize,
+    },
+    /// The instruction at the given address was about to be executed a second time since the last
+    /// time execution was resumed.
+    Cycle(usize),
+    Halted,
+    Fault(Fault),
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Command {
+    Break(Breakpoint),
+    Watch(Watchpoint),
+    /// Removes all breakpoints and watchpoints.
+    Clear,
+    Step(usize),
+    Continue,
+    /// Flips the `jmp`/`nop` at the given address, or under the cursor, and continues.
+    Flip(Option<usize>),
+    Print,
+    List,
+    Reset,
+    Help,
+    Quit,
+}
+
+impl FromStr for Command {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut words = s.split_whitespace();
+        let command = words.next().unwrap_or("");
+        let argument = words.next();
+        if let Some(extra) = words.next() {
+            return Err(format!("unexpected argument '{}'", extra));
+        }
+
+        let parse_number = |value: &str| -> Result<isize, String> {
+            value
+                .trim_start_matches('+')
+                .parse()
+                .map_err(|_| format!("invalid number '{}'", value))
+        };
+        let parse_address = |value: &str| -> Result<usize, String> {
+            value
+                .parse()
+                .map_err(|_| format!("invalid address '{}'", value))
+        };
+
+        match (command, argument) {
+            ("b", Some(arg)) | ("break", Some(arg)) => Ok(Command::Break(match arg.parse() {
+                Ok(opcode) => Breakpoint::Opcode(opcode),
+                Err(_) => Breakpoint::Address(parse_address(arg)?),
+            })),
+            ("w", None) | ("watch", None) => Ok(Command::Watch(Watchpoint::Changed)),
+            ("w", Some(arg)) | ("watch", Some(arg)) => {
+                Ok(Command::Watch(Watchpoint::Equals(parse_number(arg)?)))
+            }
+            ("clear", None) => Ok(Command::Clear),
+            ("s", None) | ("step", None) => Ok(Command::Step(1)),
+            ("s", Some(arg)) | ("step", Some(arg)) => Ok(Command::Step(parse_address(arg)?)),
+            ("c", None) | ("continue", None) => Ok(Command::Continue),
+            ("f", arg) | ("flip", arg) => Ok(Command::Flip(match arg {
+                Some(arg) => Some(parse_address(arg)?),
+                None => None,
+            })),
+            ("p", None) | ("print", None) => Ok(Command::Print),
+            ("l", None) | ("list", None) => Ok(Command::List),
+            ("reset", None) => Ok(Command::Reset),
+            ("h", None) | ("help", None) => Ok(Command::Help),
+            ("q", None) | ("quit", None) => Ok(Command::Quit),
+            ("", None) => Err("empty command".to_owned()),
+            _ => Err(format!("invalid command '{}'", s.trim())),
+        }
+    }
+}
+
+pub const HELP: &str = "\
+break <address|opcode>  stop before executing an address or any instruction with the opcode
+watch [value]           stop when the accumulator changes, or becomes the given value
+clear                   remove all breakpoints and watchpoints
+step [count]            execute a number of instructions
+continue                run until a breakpoint, watchpoint, loop or the end of the program
+flip [address]          swap the jmp/nop under the cursor, or at the address, and continue
+print                   print the cursor and accumulator
+list                    print the instructions around the cursor
+reset                   restart the program
+quit                    exit the debugger";
+
+pub struct Debugger<'a> {
+    machine: Machine<'a>,
+    breakpoints: Vec<Breakpoint>,
+    watchpoints: Vec<Watchpoint>,
+}
+
+impl<'a> Debugger<'a> {
+    pub fn new(machine: Machine<'a>) -> Self {
+        Debugger {
+            machine,
+            breakpoints: Vec::new(),
+            watchpoints: Vec::new(),
+        }
+    }
+
+    pub fn machine(&self) -> &Machine<'a> {
+        &self.machine
+    }
+
+    pub fn machine_mut(&mut self) -> &mut Machine<'a> {
+        &mut self.machine
+    }
+
+    pub fn state(&self) -> State {
+        self.machine.state()
+    }
+
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) -> usize {
+        self.breakpoints.push(breakpoint);
+        self.breakpoints.len() - 1
+    }
+
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) -> usize {
+        self.watchpoints.push(watchpoint);
+        self.watchpoints.len() - 1
+    }
+
+    pub fn clear(&mut self) {
+        self.breakpoints.clear();
+        self.watchpoints.clear();
+    }
+
+    /// Executes a single instruction, ignoring breakpoints. Returns `None` if nothing noteworthy
+    /// happened.
+    pub fn step(&mut self) -> Option<Stop> {
+        let before = self.machine.state().accumulator;
+        match self.machine.step() {
+            Err(fault) => Some(Stop::Fault(fault)),
+            Ok(status) => {
+                let after = self.machine.state().accumulator;
+                if let Some(index) = self
+                    .watchpoints
+                    .iter()
+                    .position(|w| w.matches(before, after))
+                {
+                    Some(Stop::Watchpoint {
+                        index,
+                        before,
+                        after,
+                    })
+                } else if status == Status::Halted {
+                    Some(Stop::Halted)
+                } else {
+                    None
+                }
+            }
+        }
+    }
+
+    /// Runs until a breakpoint or watchpoint triggers, an instruction is about to be repeated or
+    /// the program ends. A breakpoint on the instruction under the cursor is skipped so execution
+    /// can be resumed from it.
+    pub fn resume(&mut self) -> Stop {
+        let mut executed = vec![false; self.machine.program().len()];
+        let mut first = true;
+        loop {
+            if self.machine.status() == Status::Halted {
+                return Stop::Halted;
+            }
+            let address = match self.machine.address() {
+                Ok(address) => address,
+                Err(fault) => return Stop::Fault(fault),
+            };
+            let op = self.machine.program()[address];
+            if !first {
+                if let Some(index) = self.breakpoints.iter().position(|b| b.matches(address, op)) {
+                    return Stop::Breakpoint(index);
+                }
+            }
+            if std::mem::replace(&mut executed[address], true) {
+                return Stop::Cycle(address);
+            }
+            first = false;
+            if let Some(stop) = self.step() {
+                return stop;
+            }
+        }
+    }
+
+    /// Swaps the `jmp`/`nop` at `address` and returns the new instruction.
+    pub fn flip(&mut self, address: usize) -> Result<Op, Fault> {
+        let op = self
+            .machine
+            .program_mut()
+            .get_mut(address)
+            .ok_or(Fault::InvalidCursor(address as isize))?;
+        *op = op.flip_corruption();
+        Ok(*op)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Breakpoint, Command, Debugger, Stop, Watchpoint};
+    use crate::handheld::{parse, Machine, Opcode, EXAMPLE};
+
+    #[test]
+    fn commands() {
+        assert_eq!(
+            "break jmp".parse(),
+            Ok(Command::Break(Breakpoint::Opcode(Opcode::Jmp)))
+        );
+        assert_eq!("b 12".parse(), Ok(Command::Break(Breakpoint::Address(12))));
+        assert_eq!(
+            "watch -3".parse(),
+            Ok(Command::Watch(Watchpoint::Equals(-3)))
+        );
+        assert_eq!("flip".parse(), Ok(Command::Flip(None)));
+        assert!("step x".parse::<Command>().is_err());
+    }
+
+    #[test]
+    fn breakpoints_and_flip() {
+        let mut debugger = Debugger::new(Machine::new(parse(EXAMPLE).unwrap()));
+        debugger.add_breakpoint(Breakpoint::Address(7));
+        assert_eq!(debugger.resume(), Stop::Breakpoint(0));
+        assert_eq!(debugger.state().accumulator, 2);
+        debugger.flip(7).unwrap();
+        assert_eq!(debugger.resume(), Stop::Halted);
+        assert_eq!(debugger.state().accumulator, 8);
+    }
+
+    #[test]
+    fn cycle() {
+        let mut debugger = Debugger::new(Machine::new(parse(EXAMPLE).unwrap()));
+        assert_eq!(debugger.resume(), Stop::Cycle(1));
+        assert_eq!(debugger.state().accumulator, 5);
+    }
+
+    #[test]
+    fn watchpoints() {
+        let mut debugger = Debugger::new(Machine::new(parse(EXAMPLE).unwrap()));
+        debugger.add_watchpoint(Watchpoint::Equals(5));
+        assert_eq!(
+            debugger.resume(),
+            Stop::Watchpoint {
+                index: 0,
+                before: 2,
+                after: 5
+            }
+        );
+        assert_eq!(debugger.state().cursor, 4);
+    }
+}
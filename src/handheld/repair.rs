@@ -0,0 +1,212 @@
+//! Finds the corrupted `jmp`/`nop` instructions that keep a program from terminating, by analysing
+//! its control-flow graph instead of re-running the program for every candidate.
+
+use super::{Machine, Op, RunResult};
+use std::collections::VecDeque;
+
+/// A set of flipped instructions that make the program terminate.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Repair {
+    /// Addresses of the flipped instructions, in execution order.
+    pub flipped: Vec<usize>,
+    /// The accumulator after the repaired program terminated.
+    pub accumulator: isize,
+}
+
+/// Returns the address execution continues at after `op`, or `None` if it jumps outside of the
+/// program. An address equal to `len` means the program terminates.
+///
+/// Conditional jumps depend on the accumulator and have no single successor, callers must reject
+/// them up front.
+fn successor(address: usize, op: Op, len: usize) -> Option<usize> {
+    let next = match op {
+        Op::Acc(_) | Op::Nop(_) | Op::Mul(_) => address as isize + 1,
+        Op::Jmp(offset) => address as isize + offset,
+        Op::Hlt => len as isize,
+        Op::Jz(_) | Op::Jnz(_) => unreachable!("conditional jumps have no static successor"),
+    };
+    if next >= 0 && next <= len as isize {
+        Some(next as usize)
+    } else {
+        None
+    }
+}
+
+/// Returns for every address, and the terminal address `len`, whether execution starting there
+/// terminates without any flips.
+fn terminating(program: &[Op]) -> Vec<bool> {
+    let len = program.len();
+    let mut predecessors = vec![Vec::new(); len + 1];
+    for (address, op) in program.iter().enumerate() {
+        if let Some(next) = successor(address, *op, len) {
+            predecessors[next].push(address);
+        }
+    }
+
+    let mut terminates = vec![false; len + 1];
+    terminates[len] = true;
+    let mut queue = VecDeque::from(vec![len]);
+    while let Some(address) = queue.pop_front() {
+        for &predecessor in predecessors[address].iter() {
+            if !terminates[predecessor] {
+                terminates[predecessor] = true;
+                queue.push_back(predecessor);
+            }
+        }
+    }
+    terminates
+}
+
+fn has_conditional_jumps(program: &[Op]) -> bool {
+    program
+        .iter()
+        .any(|op| matches!(op, Op::Jz(_) | Op::Jnz(_)))
+}
+
+/// Runs the program with the instructions at `flipped` flipped, `None` if it does not terminate.
+fn run_repaired(program: &[Op], flipped: Vec<usize>) -> Option<Repair> {
+    let mut program = program.to_vec();
+    for &address in flipped.iter() {
+        program[address] = program[address].flip_corruption();
+    }
+    match Machine::new(program).run() {
+        Ok(RunResult::Completion(accumulator)) => Some(Repair {
+            flipped,
+            accumulator,
+        }),
+        _ => None,
+    }
+}
+
+/// Repairs the program by flipping at most a single instruction, in `O(n)`.
+///
+/// Every instruction from which execution terminates is found with a single backwards traversal
+/// of the control-flow graph. The flip is the first instruction on the executed path whose flipped
+/// successor is in that set.
+///
+/// Returns `None` if no single flip makes the program terminate, if the repaired program faults,
+/// or if the program contains conditional jumps.
+pub fn repair(program: &[Op]) -> Option<Repair> {
+    if has_conditional_jumps(program) {
+        return None;
+    }
+    let len = program.len();
+    let terminates = terminating(program);
+    // The flipped successors below are checked against the unflipped program, which only holds
+    // while the path itself does not terminate
+    if terminates[0] {
+        return run_repaired(program, Vec::new());
+    }
+
+    let mut executed = vec![false; len];
+    let mut address = 0;
+    while !std::mem::replace(&mut executed[address], true) {
+        let op = program[address];
+        if let Op::Jmp(_) | Op::Nop(_) = op {
+            if let Some(next) = successor(address, op.flip_corruption(), len) {
+                if terminates[next] {
+                    return run_repaired(program, vec![address]);
+                }
+            }
+        }
+        address = successor(address, op, len)?;
+    }
+    None
+}
+
+/// Repairs the program with the smallest possible number of flips, in `O(n)`.
+///
+/// Finds the shortest path from the first instruction to the end of the program where following
+/// an instruction costs nothing and following its flipped version costs one, using a 0-1
+/// breadth-first search. A shortest path never visits an address twice, so executing the program
+/// with the flips along the path follows exactly that path. Flipping every `jmp` always yields a
+/// terminating program, so a repair exists unless the program contains conditional jumps or the
+/// repaired program faults.
+pub fn repair_minimal(program: &[Op]) -> Option<Repair> {
+    if has_conditional_jumps(program) {
+        return None;
+    }
+    let len = program.len();
+    let mut flips = vec![usize::MAX; len + 1];
+    let mut came_from: Vec<Option<(usize, bool)>> = vec![None; len + 1];
+    let mut queue = VecDeque::new();
+    flips[0] = 0;
+    queue.push_back(0);
+    while let Some(address) = queue.pop_front() {
+        if address == len {
+            break;
+        }
+        let op = program[address];
+        let mut edges = vec![(op, false)];
+        if let Op::Jmp(_) | Op::Nop(_) = op {
+            edges.push((op.flip_corruption(), true));
+        }
+        for (op, flipped) in edges {
+            let next = match successor(address, op, len) {
+                Some(next) => next,
+                None => continue,
+            };
+            let cost = flips[address] + flipped as usize;
+            if cost < flips[next] {
+                flips[next] = cost;
+                came_from[next] = Some((address, flipped));
+                if flipped {
+                    queue.push_back(next);
+                } else {
+                    queue.push_front(next);
+                }
+            }
+        }
+    }
+
+    let mut flipped = Vec::new();
+    let mut address = len;
+    while let Some((previous, was_flipped)) = came_from[address] {
+        if was_flipped {
+            flipped.push(previous);
+        }
+        address = previous;
+    }
+    flipped.reverse();
+    run_repaired(program, flipped)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{repair, repair_minimal, Repair};
+    use crate::handheld::{parse, EXAMPLE};
+
+    #[test]
+    fn single_flip() {
+        let expected = Some(Repair {
+            flipped: vec![7],
+            accumulator: 8,
+        });
+        assert_eq!(repair(&parse(EXAMPLE).unwrap()), expected);
+        assert_eq!(repair_minimal(&parse(EXAMPLE).unwrap()), expected);
+    }
+
+    #[test]
+    fn already_terminates() {
+        let program = parse("jmp +2\njmp -1").unwrap();
+        let expected = Some(Repair {
+            flipped: vec![],
+            accumulator: 0,
+        });
+        assert_eq!(repair(&program), expected);
+        assert_eq!(repair_minimal(&program), expected);
+    }
+
+    #[test]
+    fn multiple_flips() {
+        let program = parse("jmp +0\nacc +2\njmp +0\nacc +3").unwrap();
+        assert_eq!(repair(&program), None);
+        assert_eq!(
+            repair_minimal(&program),
+            Some(Repair {
+                flipped: vec![0, 2],
+                accumulator: 5
+            })
+        );
+    }
+}
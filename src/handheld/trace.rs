@@ -0,0 +1,277 @@
+//! Records every executed instruction of a run so it can be inspected after the fact.
+//!
+//! A trace file has one line per executed instruction holding the address, the instruction and
+//! the accumulator before and after, separated by tabs. The last line records how the run ended.
+
+use super::{Fault, Machine, Op, RunResult};
+use std::fmt;
+use std::io::{self, BufRead, Write};
+
+/// How a traced run ended.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum End {
+    Completion(isize),
+    /// The instruction at `address` was about to be executed a second time.
+    Cycle {
+        address: usize,
+        accumulator: isize,
+    },
+    Fault(Fault),
+}
+
+/// A single executed instruction.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Step {
+    pub address: usize,
+    pub op: Op,
+    /// The accumulator before the instruction was executed.
+    pub before: isize,
+    /// The accumulator after the instruction was executed.
+    pub after: isize,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Trace {
+    pub steps: Vec<Step>,
+    pub end: End,
+}
+
+#[derive(Debug)]
+pub enum TraceError {
+    Io(io::Error),
+    Malformed { line: usize, text: String },
+    MissingEnd,
+}
+
+impl fmt::Display for TraceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TraceError::Io(e) => write!(f, "{}", e),
+            TraceError::Malformed { line, text } => {
+                write!(f, "line {}: malformed '{}'", line, text)
+            }
+            TraceError::MissingEnd => write!(f, "trace does not record how the run ended"),
+        }
+    }
+}
+
+impl std::error::Error for TraceError {}
+
+impl From<io::Error> for TraceError {
+    fn from(e: io::Error) -> Self {
+        TraceError::Io(e)
+    }
+}
+
+impl Trace {
+    /// Runs the program until it halts, faults or is about to repeat an instruction.
+    pub fn record(program: Vec<Op>) -> Trace {
+        let mut steps = Vec::new();
+        let result = {
+            let mut machine = Machine::new(program);
+            machine.on_execute(|execution| {
+                steps.push(Step {
+                    address: execution.address,
+                    op: execution.op,
+                    before: execution.before.accumulator,
+                    after: execution.after.accumulator,
+                })
+            });
+            machine.run().map(|result| (result, machine.state().cursor))
+        };
+        let end = match result {
+            Ok((RunResult::Completion(accumulator), _)) => End::Completion(accumulator),
+            Ok((RunResult::Cycle(accumulator), cursor)) => End::Cycle {
+                address: cursor as usize,
+                accumulator,
+            },
+            Err(fault) => End::Fault(fault),
+        };
+        Trace { steps, end }
+    }
+
+    /// Returns the index of the first step that executed the instruction the run looped back to.
+    pub fn loop_start(&self) -> Option<usize> {
+        match self.end {
+            End::Cycle { address, .. } => self.steps.iter().position(|s| s.address == address),
+            _ => None,
+        }
+    }
+
+    pub fn write(&self, mut w: impl Write) -> io::Result<()> {
+        writeln!(w, "# address\tinstruction\tbefore\tafter")?;
+        for step in self.steps.iter() {
+            writeln!(
+                w,
+                "{}\t{}\t{}\t{}",
+                step.address, step.op, step.before, step.after
+            )?;
+        }
+        match self.end {
+            End::Completion(accumulator) => writeln!(w, "completion\t{}", accumulator),
+            End::Cycle {
+                address,
+                accumulator,
+            } => writeln!(w, "cycle\t{}\t{}", address, accumulator),
+            End::Fault(Fault::InvalidCursor(cursor)) => writeln!(w, "fault\tcursor\t{}", cursor),
+            End::Fault(Fault::JumpOutOfBounds { address, target }) => {
+                writeln!(w, "fault\tjump\t{}\t{}", address, target)
+            }
+            End::Fault(Fault::Overflow { address }) => {
+                writeln!(w, "fault\toverflow\t{}", address)
+            }
+        }
+    }
+
+    pub fn read(r: impl BufRead) -> Result<Trace, TraceError> {
+        let mut steps = Vec::new();
+        let mut end = None;
+        for (i, line) in r.lines().enumerate() {
+            let line = line?;
+            if line.starts_with('#') || line.trim().is_empty() {
+                continue;
+            }
+            let malformed = || TraceError::Malformed {
+                line: i + 1,
+                text: line.clone(),
+            };
+            if end.is_some() {
+                return Err(malformed());
+            }
+
+            let fields: Vec<&str> = line.split('\t').collect();
+            let number = |index: usize| -> Result<isize, TraceError> {
+                fields
+                    .get(index)
+                    .and_then(|field| field.parse().ok())
+                    .ok_or_else(malformed)
+            };
+            let address = |index: usize| -> Result<usize, TraceError> {
+                fields
+                    .get(index)
+                    .and_then(|field| field.parse().ok())
+                    .ok_or_else(malformed)
+            };
+            match fields[0] {
+                "completion" if fields.len() == 2 => end = Some(End::Completion(number(1)?)),
+                "cycle" if fields.len() == 3 => {
+                    end = Some(End::Cycle {
+                        address: address(1)?,
+                        accumulator: number(2)?,
+                    })
+                }
+                "fault" if fields.len() == 3 && fields[1] == "cursor" => {
+                    end = Some(End::Fault(Fault::InvalidCursor(number(2)?)))
+                }
+                "fault" if fields.len() == 3 && fields[1] == "overflow" => {
+                    end = Some(End::Fault(Fault::Overflow {
+                        address: address(2)?,
+                    }))
+                }
+                "fault" if fields.len() == 4 && fields[1] == "jump" => {
+                    end = Some(End::Fault(Fault::JumpOutOfBounds {
+                        address: address(2)?,
+                        target: number(3)?,
+                    }))
+                }
+                _ if fields.len() == 4 => steps.push(Step {
+                    address: address(0)?,
+                    op: fields[1].parse().map_err(|_| malformed())?,
+                    before: number(2)?,
+                    after: number(3)?,
+                }),
+                _ => return Err(malformed()),
+            }
+        }
+        end.map(|end| Trace { steps, end })
+            .ok_or(TraceError::MissingEnd)
+    }
+
+    /// Writes a human readable listing of the trace. When the run ended in a cycle, the steps
+    /// that make up the loop are marked with `*` and the step the loop returns to with `>`.
+    pub fn replay(&self, mut w: impl Write) -> io::Result<()> {
+        let loop_start = self.loop_start();
+        writeln!(w, " step  address  instruction   accumulator")?;
+        for (index, step) in self.steps.iter().enumerate() {
+            let marker = match loop_start {
+                Some(start) if index == start => '>',
+                Some(start) if index > start => '*',
+                _ => ' ',
+            };
+            writeln!(
+                w,
+                "{}{:>5}  {:>7}  {:<12}  {} -> {}",
+                marker,
+                index,
+                step.address,
+                step.op.to_string(),
+                step.before,
+                step.after
+            )?;
+        }
+        match (self.end, loop_start) {
+            (End::Completion(accumulator), _) => {
+                writeln!(w, "program completed, accumulator {}", accumulator)
+            }
+            (
+                End::Cycle {
+                    address,
+                    accumulator,
+                },
+                Some(start),
+            ) => writeln!(
+                w,
+                "loop: instruction {} is about to run again (first run at step {}, loop of {} \
+                 steps), accumulator {}",
+                address,
+                start,
+                self.steps.len() - start,
+                accumulator
+            ),
+            (
+                End::Cycle {
+                    address,
+                    accumulator,
+                },
+                None,
+            ) => writeln!(
+                w,
+                "loop: instruction {} is about to run again, accumulator {}",
+                address, accumulator
+            ),
+            (End::Fault(fault), _) => writeln!(w, "fault: {}", fault),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{End, Trace, TraceError};
+    use crate::handheld::{parse, EXAMPLE};
+
+    #[test]
+    fn round_trip() {
+        let trace = Trace::record(parse(EXAMPLE).unwrap());
+        assert_eq!(
+            trace.end,
+            End::Cycle {
+                address: 1,
+                accumulator: 5
+            }
+        );
+        assert_eq!(trace.loop_start(), Some(1));
+
+        let mut file = Vec::new();
+        trace.write(&mut file).unwrap();
+        assert_eq!(Trace::read(&file[..]).unwrap(), trace);
+    }
+
+    #[test]
+    fn negative_address() {
+        let file = "0\tacc +1\t0\t1\ncycle\t-1\t1\n";
+        match Trace::read(file.as_bytes()) {
+            Err(TraceError::Malformed { line, .. }) => assert_eq!(line, 2),
+            result => panic!("expected a malformed line, got {:?}", result),
+        }
+    }
+}
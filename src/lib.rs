@@ -1,3 +1,7 @@
+pub mod frames;
+pub mod handheld;
+pub mod stats;
+
 #[macro_export]
 macro_rules! regex {
     ($re:literal $(,)?) => {{
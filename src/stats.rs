@@ -0,0 +1,165 @@
+//! Per-generation statistics of the cellular automata (days 11, 17 and 24), written as CSV so
+//! convergence can be plotted and rule variants compared.
+
+use std::collections::HashSet;
+use std::fs;
+use std::hash::Hash;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Stats {
+    pub generation: usize,
+    pub population: usize,
+    /// Cells that became alive in this generation.
+    pub births: usize,
+    /// Cells that died in this generation.
+    pub deaths: usize,
+    /// The smallest and largest coordinate of the living cells along every axis, or `None` if no
+    /// cell is alive.
+    pub bounding_box: Option<(Vec<isize>, Vec<isize>)>,
+}
+
+impl Stats {
+    /// The number of cells that changed in this generation.
+    pub fn changed(&self) -> usize {
+        self.births + self.deaths
+    }
+
+    /// Compares the living cells before and after a generation. `coordinates` returns the
+    /// position of a cell along every axis.
+    pub fn compare<P: Hash + Eq>(
+        generation: usize,
+        before: &HashSet<P>,
+        after: &HashSet<P>,
+        coordinates: impl Fn(&P) -> Vec<isize>,
+    ) -> Stats {
+        Stats {
+            generation,
+            population: after.len(),
+            births: after.difference(before).count(),
+            deaths: before.difference(after).count(),
+            bounding_box: bounding_box(after.iter().map(coordinates)),
+        }
+    }
+}
+
+/// Returns the smallest and largest coordinate along every axis.
+pub fn bounding_box(
+    mut coordinates: impl Iterator<Item = Vec<isize>>,
+) -> Option<(Vec<isize>, Vec<isize>)> {
+    let first = coordinates.next()?;
+    Some(
+        coordinates.fold((first.clone(), first), |(mut min, mut max), p| {
+            for (axis, value) in p.into_iter().enumerate() {
+                min[axis] = min[axis].min(value);
+                max[axis] = max[axis].max(value);
+            }
+            (min, max)
+        }),
+    )
+}
+
+/// Writes one CSV row per generation. Call [`StatsWriter::finish`] to flush the rows and see
+/// whether that failed.
+pub struct StatsWriter<W: Write> {
+    w: W,
+    axes: usize,
+}
+
+impl StatsWriter<BufWriter<fs::File>> {
+    pub fn create(path: impl AsRef<Path>, axes: &[&str]) -> io::Result<Self> {
+        StatsWriter::new(BufWriter::new(fs::File::create(path)?), axes)
+    }
+}
+
+impl<W: Write> StatsWriter<W> {
+    /// Writes the header, with a minimum and maximum column for every axis.
+    pub fn new(mut w: W, axes: &[&str]) -> io::Result<Self> {
+        write!(w, "generation,population,births,deaths,changed")?;
+        for axis in axes.iter() {
+            write!(w, ",min_{},max_{}", axis, axis)?;
+        }
+        writeln!(w)?;
+        Ok(StatsWriter {
+            w,
+            axes: axes.len(),
+        })
+    }
+
+    pub fn write(&mut self, stats: &Stats) -> io::Result<()> {
+        write!(
+            self.w,
+            "{},{},{},{},{}",
+            stats.generation,
+            stats.population,
+            stats.births,
+            stats.deaths,
+            stats.changed()
+        )?;
+        for axis in 0..self.axes {
+            match &stats.bounding_box {
+                Some((min, max)) => write!(self.w, ",{},{}", min[axis], max[axis])?,
+                None => write!(self.w, ",,")?,
+            }
+        }
+        writeln!(self.w)
+    }
+
+    /// Flushes the rows and returns the underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.w.flush()?;
+        Ok(self.w)
+    }
+}
+
+/// The `--stats <dir>` argument of the automata.
+#[derive(Debug, Default, Clone)]
+pub struct StatsArgs {
+    dir: Option<PathBuf>,
+}
+
+impl StatsArgs {
+    /// Takes the value of `arg` from `args` if it is the stats argument. Returns whether it was.
+    pub fn parse(&mut self, arg: &str, args: &mut impl Iterator<Item = String>) -> bool {
+        if arg != "--stats" {
+            return false;
+        }
+        self.dir = Some(args.next().expect("--stats requires a directory").into());
+        true
+    }
+
+    /// Creates `<name>.csv` in the directory, `None` if no statistics were requested.
+    pub fn writer(&self, name: &str, axes: &[&str]) -> Option<StatsWriter<BufWriter<fs::File>>> {
+        self.dir.as_ref().map(|dir| {
+            fs::create_dir_all(dir).unwrap();
+            StatsWriter::create(dir.join(format!("{}.csv", name)), axes).unwrap()
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Stats, StatsWriter};
+    use std::collections::HashSet;
+
+    #[test]
+    fn csv() {
+        let before: HashSet<(isize, isize)> = vec![(0, 0), (1, 0)].into_iter().collect();
+        let after: HashSet<(isize, isize)> = vec![(1, 0), (1, 1), (-2, 1)].into_iter().collect();
+        let stats = Stats::compare(1, &before, &after, |p| vec![p.0, p.1]);
+        let empty = Stats::compare(2, &after, &HashSet::new(), |p| vec![p.0, p.1]);
+
+        let mut csv = Vec::new();
+        let mut writer = StatsWriter::new(&mut csv, &["x", "y"]).unwrap();
+        writer.write(&stats).unwrap();
+        writer.write(&empty).unwrap();
+        writer.finish().unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "generation,population,births,deaths,changed,min_x,max_x,min_y,max_y\n\
+             1,3,2,1,3,-2,1,0,1\n\
+             2,0,0,3,3,,,,\n"
+        );
+    }
+}